@@ -0,0 +1,530 @@
+//! What is done to the selected files: removed, moved, trashed, packed
+//! into an archive, or nothing in a dry run. Library users can pick one or
+//! implement `Action` for their own, e.g. uploading before removal.
+//!
+//! ```no_run
+//! use rm_old::action::{self, Action, Target};
+//! use rm_old::arg::Config;
+//! use rm_old::scan::Scanner;
+//! use std::time::SystemTime;
+//!
+//! let config = Config::parse_config(&["rm-old", "/var/tmp/cache", "-r", "-d", "30"]).unwrap();
+//! let cutoff = config.time_window(SystemTime::now()).unwrap().older;
+//! let mut action = action::from_config(&config, cutoff).unwrap();
+//! for candidate in Scanner::new(&config).iter().flatten() {
+//!     let target = Target::from(&candidate);
+//!     if let Err(failure) = action.apply(&target) {
+//!         eprintln!("{} {}", failure, target.path.display());
+//!     }
+//! }
+//! ```
+
+use crate::archive;
+use crate::arg::{Config, TimeField};
+use crate::error::Error;
+use crate::fs::Entry;
+use crate::journal::{self, Journal};
+use crate::relocate::{self, Conflict};
+use crate::scan::Candidate;
+use crate::trash;
+use crate::unlink::{self, Expect, RemoveError};
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// A selected file or dir to act on.
+#[derive(Clone, Debug)]
+pub struct Target {
+    /// `root` joined with `rel_path`.
+    pub path:       PathBuf,
+    /// The target dir it was found in.
+    pub root:       PathBuf,
+    pub rel_path:   PathBuf,
+    /// What it was when scanned.
+    pub entry:      Entry,
+}
+
+impl From<&Candidate> for Target {
+    fn from(candidate: &Candidate) -> Target {
+        Target {
+            path:       candidate.path.clone(),
+            root:       candidate.target.clone(),
+            rel_path:   candidate.rel_path.clone(),
+            entry:      candidate.entry.clone(),
+        }
+    }
+}
+
+/// Why a target was left in place.
+#[derive(Debug)]
+pub enum Failure {
+    Canceled,
+    /// Left alone on purpose, e.g. replaced since the scan.
+    Skipped(String),
+    /// What failed and why.
+    Failed(&'static str, io::Error),
+}
+
+impl Failure {
+    /// The result in the records: "canceled", "skipped" or "failed".
+    pub fn result(&self) -> &'static str {
+        match self {
+            Failure::Canceled       => "canceled",
+            Failure::Skipped(_)     => "skipped",
+            Failure::Failed(_, _)   => "failed",
+        }
+    }
+
+    pub fn reason(&self) -> Option<String> {
+        match self {
+            Failure::Canceled           => None,
+            Failure::Skipped(why)       => Some(why.clone()),
+            Failure::Failed(_, why)     => Some(why.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Failure::Canceled           => write!(f, "Canceled:"),
+            Failure::Skipped(why)       => write!(f, "Skipped ({}):", why),
+            Failure::Failed(what, why)  => write!(f, "{} failed ({}):", what, why),
+        }
+    }
+}
+
+/// What is done to each target. `apply` returns, for each one, where it
+/// was moved to if it was, or why it was left in place.
+pub trait Action {
+    /// The action in the records, e.g. "remove".
+    fn name(&self) -> &'static str;
+
+    /// Called once with all the targets before the first `apply`; an error
+    /// means none is to be acted on.
+    fn prepare(&mut self, _targets: &[Target]) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Whether the targets are left in place, only telling what would be
+    /// done.
+    fn dry_run(&self) -> bool {
+        false
+    }
+
+    fn apply(&mut self, target: &Target) -> Result<Option<PathBuf>, Failure>;
+
+    /// The journal the moves are recorded in, if any.
+    fn journal(&self) -> Option<&Path> {
+        None
+    }
+
+    /// The archive `prepare` packed the targets into, if any.
+    fn archive(&self) -> Option<&Path> {
+        None
+    }
+
+    /// Remove the dir at `path`, left empty by the run, with --remove-empty.
+    fn remove_empty_dir(&mut self, path: &Path) -> io::Result<()> {
+        fs::remove_dir(path)
+    }
+
+    /// Bytes of the targets on the device `dev` that were acted on but
+    /// still take up space, as in a dry run. --min-free counts them as
+    /// freed.
+    fn unfreed_bytes(&self, _dev: u64) -> u64 {
+        0
+    }
+
+    /// The line telling what was done to `target`, which went to `dest`.
+    fn describe(&self, target: &Target, dest: Option<&Path>) -> String {
+        done_text(self.name(), &format!("{:?}", target.entry.name), dest)
+    }
+
+    /// The line telling what `prepare` did with `targets`, if anything.
+    fn describe_prepare(&self, _targets: &[Target]) -> Option<String> {
+        None
+    }
+}
+
+impl<A: Action + ?Sized> Action for Box<A> {
+    fn name(&self) -> &'static str {
+        (**self).name()
+    }
+    fn prepare(&mut self, targets: &[Target]) -> Result<(), Error> {
+        (**self).prepare(targets)
+    }
+    fn dry_run(&self) -> bool {
+        (**self).dry_run()
+    }
+    fn apply(&mut self, target: &Target) -> Result<Option<PathBuf>, Failure> {
+        (**self).apply(target)
+    }
+    fn journal(&self) -> Option<&Path> {
+        (**self).journal()
+    }
+    fn archive(&self) -> Option<&Path> {
+        (**self).archive()
+    }
+    fn remove_empty_dir(&mut self, path: &Path) -> io::Result<()> {
+        (**self).remove_empty_dir(path)
+    }
+    fn unfreed_bytes(&self, dev: u64) -> u64 {
+        (**self).unfreed_bytes(dev)
+    }
+    fn describe(&self, target: &Target, dest: Option<&Path>) -> String {
+        (**self).describe(target, dest)
+    }
+    fn describe_prepare(&self, targets: &[Target]) -> Option<String> {
+        (**self).describe_prepare(targets)
+    }
+}
+
+/// The action of a run with `config`. Removal checks that each target is
+/// still the one scanned and older than `cutoff`. The journal of --trash
+/// and --move-to is created here.
+pub fn from_config(config: &Config, cutoff: SystemTime) -> Result<Box<dyn Action>, Error> {
+    if config.dry_run() {
+        let name = if config.move_to().is_some() {
+            "move"
+        } else if config.trash() {
+            "trash"
+        } else {
+            "remove"
+        };
+        return Ok(Box::new(DryRun::new(name, config.move_to().map(PathBuf::from), config.archive().map(PathBuf::from))));
+    }
+
+    let journal = if config.trash() || config.move_to().is_some() {
+        let path = match config.journal() {
+            Some(path)  => PathBuf::from(path),
+            None        => journal::default_path(SystemTime::now())?,
+        };
+        Some(Journal::create(&path)?)
+    } else {
+        None
+    };
+    let delete = Delete { field: config.time_field(), cutoff };
+    Ok(match (config.move_to(), config.archive()) {
+        (Some(dir), _)      => Box::new(Move { dir: PathBuf::from(dir), conflict: config.on_conflict(), field: delete.field, cutoff, journal }),
+        _ if config.trash() => Box::new(Trash { field: delete.field, cutoff, journal }),
+        (None, Some(path))  => Box::new(Archive::new(PathBuf::from(path), delete)),
+        (None, None)        => Box::new(delete),
+    })
+}
+
+/// Leaves every target in place, reporting what `name` would have done.
+pub struct DryRun {
+    pub name:       &'static str,
+    /// Where a move would go.
+    pub move_to:    Option<PathBuf>,
+    /// The archive that would be written, whose names are checked.
+    pub archive:    Option<PathBuf>,
+    /// Bytes of the targets so far, by device.
+    unfreed:        HashMap<u64, u64>,
+}
+
+impl DryRun {
+    pub fn new(name: &'static str, move_to: Option<PathBuf>, archive: Option<PathBuf>) -> DryRun {
+        DryRun { name, move_to, archive, unfreed: HashMap::new() }
+    }
+}
+
+impl Action for DryRun {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+    fn prepare(&mut self, targets: &[Target]) -> Result<(), Error> {
+        match self.archive.as_ref() {
+            Some(path)  => archive::check_names(path, &archive_files(targets)),
+            None        => Ok(()),
+        }
+    }
+    fn dry_run(&self) -> bool {
+        true
+    }
+    fn apply(&mut self, target: &Target) -> Result<Option<PathBuf>, Failure> {
+        *self.unfreed.entry(target.entry.dev).or_insert(0) += target.entry.size;
+        Ok(self.move_to.as_ref().map(|dir| dir.join(&target.rel_path)))
+    }
+    fn remove_empty_dir(&mut self, _path: &Path) -> io::Result<()> {
+        Ok(())
+    }
+    fn unfreed_bytes(&self, dev: u64) -> u64 {
+        self.unfreed.get(&dev).copied().unwrap_or(0)
+    }
+    /// The whole path, as the target did not move.
+    fn describe(&self, target: &Target, dest: Option<&Path>) -> String {
+        done_text(self.name, &target.path.display().to_string(), dest)
+    }
+    fn describe_prepare(&self, targets: &[Target]) -> Option<String> {
+        self.archive.as_ref().map(|path| format!("Would archive: {} files -> {}", targets.len(), path.display()))
+    }
+}
+
+/// Removes the target, a file or a whole dir, if it is still the one
+/// scanned and its `field` is still older than `cutoff`.
+#[derive(Clone, Copy, Debug)]
+pub struct Delete {
+    pub field:  TimeField,
+    pub cutoff: SystemTime,
+}
+
+impl Action for Delete {
+    fn name(&self) -> &'static str {
+        "remove"
+    }
+    fn apply(&mut self, target: &Target) -> Result<Option<PathBuf>, Failure> {
+        match unlink::remove(&target.root, &target.rel_path, &expect(target, self.field, self.cutoff)) {
+            Ok(_)                           => Ok(None),
+            Err(RemoveError::Changed(why))  => Err(Failure::Skipped(why)),
+            Err(RemoveError::Io(why))       => Err(Failure::Failed("Remove", why)),
+        }
+    }
+}
+
+/// Moves the target into `dir`, keeping its path relative to its target
+/// dir, if it is still the one scanned and its `field` is still older than
+/// `cutoff`.
+pub struct Move {
+    pub dir:        PathBuf,
+    pub conflict:   Conflict,
+    pub field:      TimeField,
+    pub cutoff:     SystemTime,
+    pub journal:    Option<Journal>,
+}
+
+impl Action for Move {
+    fn name(&self) -> &'static str {
+        "move"
+    }
+    fn apply(&mut self, target: &Target) -> Result<Option<PathBuf>, Failure> {
+        let meta = fs::symlink_metadata(&target.path).ok();
+        let expect = expect(target, self.field, self.cutoff);
+        let dst = self.dir.join(&target.rel_path);
+        match relocate::move_into(&target.root, &target.rel_path, &expect, &dst, self.conflict) {
+            Ok(Some(dest))                  => {
+                add_record(&mut self.journal, journal::Action::Move, &target.path, &dest, meta)?;
+                Ok(Some(dest))
+            },
+            Ok(None)                        => Err(Failure::Skipped("destination exists".to_string())),
+            Err(RemoveError::Changed(why))  => Err(Failure::Skipped(why)),
+            Err(RemoveError::Io(why))       => Err(Failure::Failed("Move", why)),
+        }
+    }
+    fn journal(&self) -> Option<&Path> {
+        self.journal.as_ref().map(Journal::path)
+    }
+}
+
+/// Moves the target to the trash, if it is still the one scanned and its
+/// `field` is still older than `cutoff`.
+pub struct Trash {
+    pub field:      TimeField,
+    pub cutoff:     SystemTime,
+    pub journal:    Option<Journal>,
+}
+
+impl Action for Trash {
+    fn name(&self) -> &'static str {
+        "trash"
+    }
+    fn apply(&mut self, target: &Target) -> Result<Option<PathBuf>, Failure> {
+        let meta = fs::symlink_metadata(&target.path).ok();
+        match trash::trash_checked(&target.root, &target.rel_path, &expect(target, self.field, self.cutoff)) {
+            Ok(dest)                        => {
+                add_record(&mut self.journal, journal::Action::Trash, &target.path, &dest, meta)?;
+                Ok(Some(dest))
+            },
+            Err(RemoveError::Changed(why))  => Err(Failure::Skipped(why)),
+            Err(RemoveError::Io(why))       => Err(Failure::Failed("Trash", why)),
+        }
+    }
+    fn journal(&self) -> Option<&Path> {
+        self.journal.as_ref().map(Journal::path)
+    }
+}
+
+/// Packs all the targets into a new archive at `path` and checks it, then
+/// removes them one by one with `delete`. Only the targets packed by
+/// `prepare` are removed.
+pub struct Archive {
+    pub path:   PathBuf,
+    pub delete: Delete,
+    /// Device and inode of the packed targets.
+    archived:   HashSet<(u64, u64)>,
+}
+
+impl Archive {
+    pub fn new(path: PathBuf, delete: Delete) -> Archive {
+        Archive { path, delete, archived: HashSet::new() }
+    }
+}
+
+impl Action for Archive {
+    fn name(&self) -> &'static str {
+        "remove"
+    }
+    fn prepare(&mut self, targets: &[Target]) -> Result<(), Error> {
+        archive::create(&self.path, &archive_files(targets))?;
+        self.archived = targets.iter().map(|target| (target.entry.dev, target.entry.ino)).collect();
+        Ok(())
+    }
+    fn archive(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+    fn apply(&mut self, target: &Target) -> Result<Option<PathBuf>, Failure> {
+        if !self.archived.contains(&(target.entry.dev, target.entry.ino)) {
+            return Err(Failure::Skipped(format!("not in {}", self.path.display())));
+        }
+        self.delete.apply(target)
+    }
+    fn describe_prepare(&self, targets: &[Target]) -> Option<String> {
+        Some(format!("Archived: {} files -> {}", targets.len(), self.path.display()))
+    }
+}
+
+/// "Removed: a.log", or with `dest` "Moved: a.log -> dest", for the action
+/// `name` on the target shown as `shown`.
+fn done_text(name: &str, shown: &str, dest: Option<&Path>) -> String {
+    let done = match name {
+        "move"  => "Moved",
+        "trash" => "Trashed",
+        _       => "Removed",
+    };
+    match dest {
+        Some(dest)  => format!("{}: {} -> {}", done, shown, dest.display()),
+        None        => format!("{}: {}", done, shown),
+    }
+}
+
+/// What `target` must still be to be acted on.
+fn expect(target: &Target, field: TimeField, cutoff: SystemTime) -> Expect {
+    Expect { dev: target.entry.dev, ino: target.entry.ino, field, cutoff }
+}
+
+/// The files of `targets` with their names in an archive.
+fn archive_files(targets: &[Target]) -> Vec<(PathBuf, PathBuf)> {
+    targets.iter().map(|target| (target.path.clone(), target.rel_path.clone())).collect()
+}
+
+/// An action made of a closure.
+pub struct FnAction<F> {
+    name:   &'static str,
+    f:      F,
+}
+
+pub fn from_fn<F>(name: &'static str, f: F) -> FnAction<F>
+where
+    F: FnMut(&Target) -> Result<Option<PathBuf>, Failure>,
+{
+    FnAction { name, f }
+}
+
+impl<F> Action for FnAction<F>
+where
+    F: FnMut(&Target) -> Result<Option<PathBuf>, Failure>,
+{
+    fn name(&self) -> &'static str {
+        self.name
+    }
+    fn apply(&mut self, target: &Target) -> Result<Option<PathBuf>, Failure> {
+        (self.f)(target)
+    }
+}
+
+/// Journal a file moved from `src` to `dest`; `meta` is its metadata from
+/// before the move.
+fn add_record(journal: &mut Option<Journal>, action: journal::Action, src: &Path, dest: &Path, meta: Option<fs::Metadata>) -> Result<(), Failure> {
+    if let (Some(journal), Some(meta)) = (journal.as_mut(), meta) {
+        journal.add(&journal::Record::new(action, src, dest, &meta)).map_err(|err| {
+            let why = io::Error::from(err);
+            Failure::Failed("Journal", io::Error::new(why.kind(), format!("{}: {}, moved but not journaled", journal.path().display(), why)))
+        })?;
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod test{
+    use super::*;
+    use crate::testutil::TempDir;
+    use std::fs::File;
+    use std::time::Duration;
+
+    #[test]
+    fn test_action() {
+        let dir = TempDir::new("action");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        let old = SystemTime::now() - Duration::from_secs(3600);
+        let target = |rel: &str| -> Target {
+            let path = dir.join(rel);
+            File::create(&path).unwrap().set_modified(old).unwrap();
+            let meta = fs::symlink_metadata(&path).unwrap();
+            let (dev, ino) = crate::fs::get_file_id(&meta);
+            Target {
+                path,
+                root:       dir.to_path_buf(),
+                rel_path:   PathBuf::from(rel),
+                entry:      Entry { name: Path::new(rel).file_name().unwrap().into(), time: old, size: 0, dev, ino },
+            }
+        };
+
+        // A dry run leaves the file, telling where a move would go.
+        let a = target("sub/a.log");
+        let mut dry_run = DryRun::new("move", Some(dir.join("moved")), None);
+        assert_eq!(Some(dir.join("moved/sub/a.log")), dry_run.apply(&a).unwrap());
+        assert!(a.path.exists());
+        assert_eq!(format!("Moved: {} -> {}", a.path.display(), dir.join("moved/sub/a.log").display()),
+                   dry_run.describe(&a, Some(&dir.join("moved/sub/a.log"))));
+        let mut big = a.clone();
+        big.entry.size = 100;
+        dry_run.apply(&big).unwrap();
+        assert_eq!(100, dry_run.unfreed_bytes(a.entry.dev));
+        assert!(dry_run.dry_run());
+        assert_eq!(None, dry_run.describe_prepare(std::slice::from_ref(&a)));
+
+        let mut delete = Delete { field: TimeField::Mtime, cutoff: SystemTime::now() };
+        assert_eq!(None, delete.apply(&a).unwrap());
+        assert!(!a.path.exists());
+        assert!(matches!(delete.apply(&a), Err(Failure::Failed("Remove", why)) if why.kind() == io::ErrorKind::NotFound));
+
+        // Still the file, but no longer older than the cutoff.
+        let b = target("b.log");
+        let mut strict = Delete { field: TimeField::Mtime, cutoff: old - Duration::from_secs(60) };
+        assert!(matches!(strict.apply(&b), Err(Failure::Skipped(_))));
+
+        let mut moved = Move { dir: dir.join("moved"), conflict: Conflict::Skip, field: TimeField::Mtime, cutoff: SystemTime::now(), journal: None };
+        assert_eq!(Some(dir.join("moved/b.log")), moved.apply(&b).unwrap());
+        let b = target("b.log");
+        assert!(matches!(moved.apply(&b), Err(Failure::Skipped(why)) if why == "destination exists"));
+
+        // Only what prepare packed is removed.
+        let c = target("c.log");
+        let d = target("d.log");
+        let mut archive = Archive::new(dir.join("out.tar"), Delete { field: TimeField::Mtime, cutoff: SystemTime::now() });
+        assert!(matches!(archive.apply(&c), Err(Failure::Skipped(_))));
+        archive.prepare(std::slice::from_ref(&c)).unwrap();
+        assert_eq!(Some(dir.join("out.tar").as_path()), archive.archive());
+        assert_eq!(Some(format!("Archived: 1 files -> {}", dir.join("out.tar").display())),
+                   archive.describe_prepare(std::slice::from_ref(&c)));
+        assert!(matches!(archive.apply(&d), Err(Failure::Skipped(_))));
+        assert!(d.path.exists());
+        assert_eq!(None, archive.apply(&c).unwrap());
+        assert!(!c.path.exists());
+
+        let mut seen = Vec::new();
+        let mut custom = from_fn("upload", |target: &Target| {
+            seen.push(target.rel_path.clone());
+            Ok(None)
+        });
+        assert_eq!("upload", custom.name());
+        assert_eq!(None, custom.apply(&b).unwrap());
+        assert_eq!(vec![PathBuf::from("b.log")], seen);
+    }
+}
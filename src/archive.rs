@@ -0,0 +1,218 @@
+//! Pack files into a tar archive, optionally compressed, before they are
+//! removed.
+
+use crate::error::Error;
+
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Compression of the archive, chosen by the extension of its path.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+    None,
+    #[cfg(feature = "gzip")]
+    Gzip,
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Compression {
+    /// ".tar", ".tar.gz"/".tgz" or ".tar.zst"/".tzst".
+    pub fn from_path(path: &str) -> Result<Compression, Error> {
+        #[cfg(not(all(feature = "gzip", feature = "zstd")))]
+        let without = |feature: &str| {
+            Error::InvalidArgument(format!("rm-old --archive: {} needs the {} cargo feature, which is not enabled", path, feature))
+        };
+
+        if path.ends_with(".tar") {
+            Ok(Compression::None)
+        } else if path.ends_with(".tar.gz") || path.ends_with(".tgz") {
+            #[cfg(feature = "gzip")]
+            let gzip = Ok(Compression::Gzip);
+            #[cfg(not(feature = "gzip"))]
+            let gzip = Err(without("gzip"));
+            gzip
+        } else if path.ends_with(".tar.zst") || path.ends_with(".tzst") {
+            #[cfg(feature = "zstd")]
+            let zstd = Ok(Compression::Zstd);
+            #[cfg(not(feature = "zstd"))]
+            let zstd = Err(without("zstd"));
+            zstd
+        } else {
+            Err(Error::InvalidArgument(format!("rm-old --archive: Illegal value: {} (.tar, .tar.gz, .tgz, .tar.zst or .tzst)", path)))
+        }
+    }
+}
+
+/// Write `files`, pairs of a path and its name in the archive, into a new
+/// archive at `path`, then read it back to check that every file is in it
+/// whole. On failure the partial archive is removed. Two files with the
+/// same name fail before anything is written, as one would hide the other.
+pub fn create(path: &Path, files: &[(PathBuf, PathBuf)]) -> Result<(), Error> {
+    let compression = Compression::from_path(&path.to_string_lossy())?;
+    check_names(path, files)?;
+    let file = File::options().write(true).create_new(true).open(path)
+        .map_err(|why| Error::io(path, why))?;
+
+    let result = write(file, compression, files)
+        .and_then(|_| verify(path, compression, files))
+        .map_err(|why| Error::io(path, why));
+    if result.is_err() {
+        let _ = fs::remove_file(path);
+    }
+    result
+}
+
+/// Fail if two of `files` would have the same name in the archive at `path`.
+pub fn check_names(path: &Path, files: &[(PathBuf, PathBuf)]) -> Result<(), Error> {
+    let mut names = HashSet::new();
+    for (src, name) in files.iter() {
+        if !names.insert(name) {
+            let why = format!("{} and another file would both be {} in it", src.display(), name.display());
+            return Err(Error::io(path, io::Error::new(ErrorKind::InvalidInput, why)));
+        }
+    }
+    Ok(())
+}
+
+fn write(file: File, compression: Compression, files: &[(PathBuf, PathBuf)]) -> io::Result<()> {
+    let out = BufWriter::new(file);
+    let out = match compression {
+        Compression::None   => write_tar(out, files)?,
+        #[cfg(feature = "gzip")]
+        Compression::Gzip   => {
+            write_tar(flate2::write::GzEncoder::new(out, flate2::Compression::default()), files)?.finish()?
+        },
+        #[cfg(feature = "zstd")]
+        Compression::Zstd   => write_tar(zstd::Encoder::new(out, 0)?, files)?.finish()?,
+    };
+    let file = out.into_inner().map_err(|why| why.into_error())?;
+    file.sync_all()
+}
+
+fn write_tar<W: Write>(out: W, files: &[(PathBuf, PathBuf)]) -> io::Result<W> {
+    let mut builder = tar::Builder::new(out);
+    // Keep symlinks as they are rather than packing their targets.
+    builder.follow_symlinks(false);
+    for (src, name) in files.iter() {
+        builder.append_path_with_name(src, name)?;
+    }
+    builder.into_inner()
+}
+
+/// Read the archive back, checking names, sizes and that all data decodes.
+fn verify(path: &Path, compression: Compression, files: &[(PathBuf, PathBuf)]) -> io::Result<()> {
+    let broken = |why: String| io::Error::new(ErrorKind::InvalidData, format!("broken archive: {}", why));
+
+    let file = File::open(path).map_err(|why| broken(why.to_string()))?;
+    let input: Box<dyn Read> = match compression {
+        Compression::None   => Box::new(BufReader::new(file)),
+        #[cfg(feature = "gzip")]
+        Compression::Gzip   => Box::new(flate2::read::GzDecoder::new(BufReader::new(file))),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd   => Box::new(zstd::Decoder::new(file).map_err(|why| broken(why.to_string()))?),
+    };
+
+    let mut archive = tar::Archive::new(input);
+    let mut expected = files.iter();
+    for entry in archive.entries().map_err(|why| broken(why.to_string()))? {
+        let mut entry = entry.map_err(|why| broken(why.to_string()))?;
+        let (src, name) = match expected.next() {
+            Some(file)  => file,
+            None        => return Err(broken("unexpected extra entry".to_string())),
+        };
+        let entry_path = entry.path().map_err(|why| broken(why.to_string()))?.into_owned();
+        if entry_path != *name {
+            return Err(broken(format!("expected {}, found {}", name.display(), entry_path.display())));
+        }
+        let meta = fs::symlink_metadata(src).map_err(|why| broken(format!("{}: {}", src.display(), why)))?;
+        let size = if meta.file_type().is_file() { meta.len() } else { 0 };
+        let read = io::copy(&mut entry, &mut io::sink()).map_err(|why| broken(why.to_string()))?;
+        if read != size {
+            return Err(broken(format!("{} has {} of {} bytes", name.display(), read, size)));
+        }
+    }
+    if let Some((_, name)) = expected.next() {
+        return Err(broken(format!("{} is missing", name.display())));
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod test{
+    use super::*;
+    use crate::testutil::TempDir;
+
+    fn list(path: &Path, compression: Compression) -> Vec<(String, String)> {
+        let file = File::open(path).unwrap();
+        let input: Box<dyn Read> = match compression {
+            Compression::None   => Box::new(file),
+            #[cfg(feature = "gzip")]
+            Compression::Gzip   => Box::new(flate2::read::GzDecoder::new(file)),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd   => Box::new(zstd::Decoder::new(file).unwrap()),
+        };
+        let mut archive = tar::Archive::new(input);
+        archive.entries().unwrap().map(|entry| {
+            let mut entry = entry.unwrap();
+            let name = entry.path().unwrap().to_string_lossy().into_owned();
+            let mut data = String::new();
+            entry.read_to_string(&mut data).unwrap();
+            (name, data)
+        }).collect()
+    }
+
+    #[test]
+    fn test_from_path() {
+        assert_eq!(Compression::None, Compression::from_path("a.tar").unwrap());
+        assert!(Compression::from_path("a.zip").is_err());
+        #[cfg(feature = "gzip")]
+        assert_eq!(Compression::Gzip, Compression::from_path("a.tgz").unwrap());
+        #[cfg(not(feature = "zstd"))]
+        assert!(Compression::from_path("a.tar.zst").is_err());
+    }
+
+    #[test]
+    fn test_create() {
+        let base = TempDir::new("archive");
+        fs::create_dir_all(base.join("target/sub")).unwrap();
+        fs::write(base.join("target/a"), "aaa").unwrap();
+        fs::write(base.join("target/sub/b"), "b").unwrap();
+        let files = vec![(base.join("target/a"), PathBuf::from("a")),
+                         (base.join("target/sub/b"), PathBuf::from("sub/b"))];
+
+        let mut names = vec!["out.tar"];
+        if cfg!(feature = "gzip") {
+            names.push("out.tar.gz");
+        }
+        if cfg!(feature = "zstd") {
+            names.push("out.tar.zst");
+        }
+        for name in names {
+            let path = base.join(name);
+            create(&path, &files).unwrap();
+            assert_eq!(vec![("a".to_string(), "aaa".to_string()), ("sub/b".to_string(), "b".to_string())],
+                       list(&path, Compression::from_path(name).unwrap()));
+            // An existing archive is never overwritten.
+            assert!(create(&path, &files).is_err());
+            assert!(path.exists());
+        }
+
+        // A missing file fails and leaves no partial archive.
+        let missing = vec![(base.join("target/a"), PathBuf::from("a")),
+                           (base.join("target/none"), PathBuf::from("none"))];
+        assert!(create(&base.join("bad.tar"), &missing).is_err());
+        assert!(!base.join("bad.tar").exists());
+
+        // The same name from two targets is refused before writing.
+        fs::create_dir_all(base.join("other")).unwrap();
+        fs::write(base.join("other/a"), "other").unwrap();
+        let twice = vec![(base.join("target/a"), PathBuf::from("a")),
+                         (base.join("other/a"), PathBuf::from("a"))];
+        assert!(create(&base.join("twice.tar"), &twice).unwrap_err().to_string().contains("other/a"));
+        assert!(!base.join("twice.tar").exists());
+    }
+}
@@ -1,10 +1,66 @@
-use std::path::Path;
+use crate::archive;
+use crate::disk::Watermark;
+use crate::error::Error;
+use crate::filter::{Age, All, And, Filter};
+use crate::glob::Pattern;
+use crate::matcher::NameRegex;
+use crate::relocate::Conflict;
+use crate::report::Format;
+use crate::retention::Policy;
+use crate::size;
+use crate::time::{self, Cutoff, TimeWindow};
+
+use std::ffi::{OsStr, OsString};
+use std::num::NonZeroUsize;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// Which timestamp of a file is used to decide its age.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimeField {
+    Atime,
+    Mtime,
+    Ctime,
+    Btime,
+}
+
+impl TimeField {
+    pub fn parse(s: &str) -> Result<TimeField, Error> {
+        match s {
+            "atime" => Ok(TimeField::Atime),
+            "mtime" => Ok(TimeField::Mtime),
+            "ctime" => Ok(TimeField::Ctime),
+            "btime" => Ok(TimeField::Btime),
+            _       => Err(Error::InvalidArgument(format!("rm-old --time-field: Illegal value: {} (atime, mtime, ctime or btime)", s))),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TimeField::Atime => "atime",
+            TimeField::Mtime => "mtime",
+            TimeField::Ctime => "ctime",
+            TimeField::Btime => "btime",
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct Config {
-    target_path:    Vec<String>,
-    duration_days:  u64,
-    change_days:    bool,
+    target_path:    Vec<PathBuf>,
+    older_than:     Option<Cutoff>,
+    newer_than:     Option<Cutoff>,
+    time_field:     TimeField,
+    policy:         Policy,
+    max_total_size: Option<u64>,
+    min_free:       Option<Watermark>,
+    include:        Vec<Pattern>,
+    exclude:        Vec<Pattern>,
+    regex:          Vec<NameRegex>,
+    exclude_regex:  Vec<NameRegex>,
+    pending_opt:    Option<String>,
     do_intr:        bool,
     assume_yes:     bool,
     recursion:      bool,
@@ -12,14 +68,41 @@ pub struct Config {
     dry_run:        bool,
     remove_dir:     bool,
     remove_empty:   bool,
+    trash:          bool,
+    move_to:        Option<String>,
+    on_conflict:    Conflict,
+    archive:        Option<String>,
+    journal:        Option<String>,
+    output:         Format,
+    print0:         bool,
+    from_file:      Option<String>,
+    null:           bool,
+    jobs:           Option<usize>,
+    fail_fast:      bool,
+    filters:        Vec<Arc<dyn Filter>>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config::new()
+    }
 }
 
 impl Config {
     pub fn new () -> Config {
         Config {
             target_path:    Vec::new(),
-            duration_days:  60,
-            change_days:    false,
+            older_than:     None,
+            newer_than:     None,
+            time_field:     TimeField::Mtime,
+            policy:         Policy::default(),
+            max_total_size: None,
+            min_free:       None,
+            include:        Vec::new(),
+            exclude:        Vec::new(),
+            regex:          Vec::new(),
+            exclude_regex:  Vec::new(),
+            pending_opt:    None,
             do_intr:        false,
             assume_yes:     false,
             recursion:      false,
@@ -27,15 +110,60 @@ impl Config {
             dry_run:        false,
             remove_dir:     false,
             remove_empty:   false,
+            trash:          false,
+            move_to:        None,
+            on_conflict:    Conflict::Skip,
+            archive:        None,
+            journal:        None,
+            output:         Format::Text,
+            print0:         false,
+            from_file:      None,
+            null:           false,
+            jobs:           None,
+            fail_fast:      false,
+            filters:        Vec::new(),
         }
     }
 
-    pub fn print(&self) -> () {
-        if self.verbose {
+    pub fn print(&self) {
+        if self.verbose && self.output == Format::Text && !self.print0 {
             for path in self.target_path.iter() {
-                println!("target_path   : {}", path);
+                println!("target_path   : {}", path.display());
+            }
+            if let Some(from_file) = self.from_file.as_ref() {
+                println!("from_file     : {}{}", from_file, if self.null { " (NUL separated)" } else { "" });
+            }
+            println!("older_than    : {}", self.older_than().describe());
+            if let Some(newer_than) = self.newer_than {
+                println!("newer_than    : {}", newer_than.describe());
+            }
+            println!("time_field    : {}", self.time_field.name());
+            let rules = [("keep_last     ", self.policy.last), ("keep_daily    ", self.policy.daily),
+                         ("keep_weekly   ", self.policy.weekly), ("keep_monthly  ", self.policy.monthly),
+                         ("keep_yearly   ", self.policy.yearly)];
+            for (name, count) in rules.iter() {
+                if let Some(count) = count {
+                    println!("{}: {}", name, count);
+                }
+            }
+            if let Some(max_total_size) = self.max_total_size {
+                println!("max_total_size: {}", size::format_size(max_total_size));
+            }
+            if let Some(min_free) = self.min_free {
+                println!("min_free      : {}", min_free.describe());
+            }
+            for pattern in self.include.iter() {
+                println!("include       : {}", pattern.as_str());
+            }
+            for pattern in self.exclude.iter() {
+                println!("exclude       : {}", pattern.as_str());
+            }
+            for regex in self.regex.iter() {
+                println!("regex         : {}", regex.as_str());
+            }
+            for regex in self.exclude_regex.iter() {
+                println!("exclude_regex : {}", regex.as_str());
             }
-            println!("duration_days : {}", self.duration_days);
             if self.do_intr {
                 println!("interaction   : yes");
             } else {
@@ -47,63 +175,246 @@ impl Config {
                 println!("assume_yes    : no");
             }
             if self.recursion {
-                println!("recursion     : yes");
+                println!("recursion     : yes (jobs: {})", self.jobs());
             } else {
                 println!("recursion     : no");
             }
+            if self.fail_fast {
+                println!("fail_fast     : yes");
+            }
             if self.dry_run {
                 println!("dry_run       : yes");
             } else {
                 println!("dry_run       : no");
             }
-            println!("");
+            if self.trash {
+                println!("action        : trash");
+            }
+            if let Some(move_to) = self.move_to.as_ref() {
+                println!("action        : move to {} (on conflict: {})", move_to, self.on_conflict.name());
+            }
+            if let Some(archive) = self.archive.as_ref() {
+                println!("action        : archive into {}, then remove", archive);
+            }
+            if let Some(journal) = self.journal.as_ref() {
+                println!("journal       : {}", journal);
+            }
+            println!();
         }
     }
 
-    pub fn parse_config(args: &[String]) -> Result<Config, String> {
+    /// Parse the command line `args`. The dir paths need not be UTF-8, the
+    /// options and their values must be.
+    pub fn parse_config<S: AsRef<OsStr>>(args: &[S]) -> Result<Config, Error> {
+        let config = Config::parse(args)?;
+        config.check_stdin()?;
+        Ok(config)
+    }
+
+    /// The answers to the prompts would be read from the list.
+    fn check_stdin(&self) -> Result<(), Error> {
+        if self.from_file.as_deref() == Some("-") && !self.assume_yes && !self.print0 {
+            return Err(Error::InvalidArgument("rm-old --from-file -: stdin holds the list, so -y is needed.".to_string()));
+        }
+        Ok(())
+    }
+
+    fn parse<S: AsRef<OsStr>>(args: &[S]) -> Result<Config, Error> {
         let mut config = Config::new();
-    
-        for arg in args[1..].iter() {
-            if "--help" == arg || "-h" == arg {
-                return Err(show_help());
-            } else if !config.change_days && '-' == arg.as_str().chars().nth(0).unwrap() {
-                match get_option(arg, &mut config) {
-                    Ok(_)  => {},
-                    Err(err_msg)          => {return Err(err_msg);},
+
+        for arg in args[1..].iter().map(AsRef::as_ref) {
+            let text = arg.to_str();
+            if let Some(opt) = config.pending_opt.take() {
+                match text {
+                    Some(value) => set_option_value(&opt, value, &mut config)?,
+                    None        => return Err(Error::InvalidArgument(format!("rm-old {}: Illegal value: {}", opt, arg.to_string_lossy()))),
                 }
+            } else if text == Some("--help") || text == Some("-h") {
+                return Err(Error::Help(show_help()));
+            } else if let Some(arg) = text.filter(|arg| arg.starts_with('-')) {
+                get_option(arg, &mut config)?;
             } else {
-                if config.change_days {
-                    let days = arg.parse::<u64>();
-                    match days {
-                        Ok(num)   => {
-                            config.duration_days = num;
-                            config.change_days = false;
-                        },
-                        Err(_)    => {
-                            return Err(format!("rm-old -d: Illegal value: {}", arg));
-                        },
-                    }
-                } else {
-                    match get_path(arg, &mut config){
-                        Ok(_)  => {},
-                        Err(err_msg)          => {return Err(err_msg);},
-                    }
-                }
+                get_path(arg, &mut config)?;
             }
         }
-    
-        if config.change_days {
-            return Err("rm-old -d: Input duration days after -d.".to_string());
-        } else if config.target_path.is_empty(){
-            config.target_path.push(".".to_string());
+
+        config.check()?;
+
+        if let Some(opt) = config.pending_opt {
+            if opt == "-d" {
+                return Err(Error::InvalidArgument("rm-old -d: Input duration days after -d.".to_string()));
+            }
+            return Err(Error::InvalidArgument(format!("rm-old {}: Input value after {}.", opt, opt)));
+        } else if config.target_path.is_empty() && config.from_file.is_none() {
+            config.target_path.push(PathBuf::from("."));
         }
         Ok(config)
     }
 
-    pub fn get_duration_days(&self) -> u64 {
-        self.duration_days
+    /// Check that the options go together.
+    fn check(&self) -> Result<(), Error> {
+        if self.max_total_size.is_some() && self.remove_dir {
+            return Err(Error::InvalidArgument("rm-old --max-total-size: can not be used with --remove-dir.".to_string()));
+        }
+        if self.min_free.is_some() && self.remove_dir {
+            return Err(Error::InvalidArgument("rm-old --min-free: can not be used with --remove-dir.".to_string()));
+        }
+        if self.move_to.is_some() && self.trash {
+            return Err(Error::InvalidArgument("rm-old --move-to: can not be used with --trash.".to_string()));
+        }
+        if self.archive.is_some() {
+            // Every file in the archive must be removed afterwards, so the
+            // options choosing them on the fly and the other actions are out.
+            let conflict = if self.trash {
+                Some("--trash")
+            } else if self.move_to.is_some() {
+                Some("--move-to")
+            } else if self.remove_dir {
+                Some("--remove-dir")
+            } else if self.min_free.is_some() {
+                Some("--min-free")
+            } else if self.do_intr {
+                Some("-i")
+            } else {
+                None
+            };
+            if let Some(opt) = conflict {
+                return Err(Error::InvalidArgument(format!("rm-old --archive: can not be used with {}.", opt)));
+            }
+        }
+        if self.journal.is_some() && !self.trash && self.move_to.is_none() {
+            return Err(Error::InvalidArgument("rm-old --journal: needs --trash or --move-to.".to_string()));
+        }
+        if self.print0 {
+            // Only the list is printed, nothing is done.
+            let conflict = if self.do_intr {
+                Some("-i")
+            } else if self.trash {
+                Some("--trash")
+            } else if self.move_to.is_some() {
+                Some("--move-to")
+            } else if self.archive.is_some() {
+                Some("--archive")
+            } else if self.output != Format::Text {
+                Some("--output")
+            } else {
+                None
+            };
+            if let Some(opt) = conflict {
+                return Err(Error::InvalidArgument(format!("rm-old --print0: can not be used with {}.", opt)));
+            }
+        }
+        if self.null && self.from_file.is_none() {
+            return Err(Error::InvalidArgument("rm-old --null: needs --from-file.".to_string()));
+        }
+        if self.from_file.is_some() {
+            // The listed files are the targets, there are no dirs to search
+            // or to size up.
+            let conflict = if !self.target_path.is_empty() {
+                Some("a dir_path")
+            } else if self.recursion {
+                Some("-r")
+            } else if self.remove_dir {
+                Some("--remove-dir")
+            } else if self.max_total_size.is_some() {
+                Some("--max-total-size")
+            } else if self.min_free.is_some() {
+                Some("--min-free")
+            } else {
+                None
+            };
+            if let Some(opt) = conflict {
+                return Err(Error::InvalidArgument(format!("rm-old --from-file: can not be used with {}.", opt)));
+            }
+        }
+        Ok(())
+    }
+
+    /// `rm-old plan [dir_path] [option] -o plan.json`: the options of a run
+    /// that only removes, plus where the plan is written ("-" for stdout).
+    /// `args` is the whole command line, "plan" being `args[1]`.
+    pub fn parse_plan<S: AsRef<OsStr>>(args: &[S]) -> Result<(Config, String), Error> {
+        let mut rest: Vec<OsString> = vec![args[0].as_ref().to_os_string()];
+        let mut output = None;
+        let mut iter = args[2..].iter().map(AsRef::as_ref);
+        while let Some(arg) = iter.next() {
+            if arg == "-o" {
+                match iter.next().map(|path| path.to_str()) {
+                    Some(Some(path))    => output = Some(path.to_string()),
+                    Some(None)          => return Err(Error::InvalidArgument("rm-old plan -o: the plan file must be a UTF-8 path.".to_string())),
+                    None                => return Err(Error::InvalidArgument("rm-old plan -o: Input the plan file after -o.".to_string())),
+                }
+            } else {
+                rest.push(arg.to_os_string());
+            }
+        }
+
+        // Nothing is asked, so the list may come from stdin without -y.
+        let config = Config::parse(&rest)?;
+        if config.trash || config.move_to.is_some() || config.archive.is_some() {
+            return Err(Error::InvalidArgument("rm-old plan: only removal can be planned, not --trash, --move-to or --archive.".to_string()));
+        }
+        if config.do_intr {
+            return Err(Error::InvalidArgument("rm-old plan: can not be used with -i.".to_string()));
+        }
+        // The plan is JSON already; what is printed besides is text.
+        if config.output != Format::Text {
+            return Err(Error::InvalidArgument("rm-old plan: can not be used with --output.".to_string()));
+        }
+        match output {
+            Some(output)    => Ok((config, output)),
+            None            => Err(Error::InvalidArgument("rm-old plan: Input the plan file with -o.".to_string())),
+        }
+    }
+
+    /// Resolve `older_than`/`newer_than` against `now`.
+    pub fn time_window(&self, now: SystemTime) -> Result<TimeWindow, Error> {
+        let newer = match self.newer_than {
+            Some(cutoff)    => Some(cutoff.to_time(now).map_err(Error::ClockSkew)?),
+            None            => None,
+        };
+        Ok(TimeWindow {
+            older:  self.older_than().to_time(now).map_err(Error::ClockSkew)?,
+            newer,
+        })
+    }
+    /// Without -d/--older-than the age limit is 60 days, or none at all
+    /// when only --keep-* retention rules, --max-total-size or --min-free
+    /// are given.
+    fn older_than(&self) -> Cutoff {
+        match self.older_than {
+            Some(cutoff)                        => cutoff,
+            None if !self.policy.is_empty()
+                || self.max_total_size.is_some()
+                || self.min_free.is_some()      => Cutoff::Age(Duration::from_secs(0)),
+            None                                => Cutoff::Age(Duration::from_secs(60 * 86400)),
+        }
+    }
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
+    pub fn max_total_size(&self) -> Option<u64> {
+        self.max_total_size
+    }
+    pub fn min_free(&self) -> Option<Watermark> {
+        self.min_free
+    }
+    pub fn include(&self) -> &[Pattern] {
+        &self.include
+    }
+    pub fn exclude(&self) -> &[Pattern] {
+        &self.exclude
+    }
+    pub fn regex(&self) -> &[NameRegex] {
+        &self.regex
     }
-    pub fn get_target_path(&self) -> Vec<String> {
+    pub fn exclude_regex(&self) -> &[NameRegex] {
+        &self.exclude_regex
+    }
+    pub fn time_field(&self) -> TimeField {
+        self.time_field
+    }
+    pub fn get_target_path(&self) -> Vec<PathBuf> {
         self.target_path.clone()
     }
     pub fn do_intr(&self) -> bool {
@@ -127,19 +438,348 @@ impl Config {
     pub fn remove_empty(&self) -> bool {
         self.remove_empty
     }
+    pub fn trash(&self) -> bool {
+        self.trash
+    }
+    pub fn move_to(&self) -> Option<&str> {
+        self.move_to.as_deref()
+    }
+    pub fn on_conflict(&self) -> Conflict {
+        self.on_conflict
+    }
+    pub fn archive(&self) -> Option<&str> {
+        self.archive.as_deref()
+    }
+    pub fn journal(&self) -> Option<&str> {
+        self.journal.as_deref()
+    }
+    pub fn output(&self) -> Format {
+        self.output
+    }
+    /// Number of threads scanning the dirs with -r, by default one per CPU.
+    pub fn jobs(&self) -> usize {
+        self.jobs.unwrap_or_else(|| thread::available_parallelism().map(NonZeroUsize::get).unwrap_or(1))
+    }
+    pub fn print0(&self) -> bool {
+        self.print0
+    }
+    /// File listing the paths to consider instead of scanning dirs, "-" for
+    /// stdin.
+    pub fn from_file(&self) -> Option<&str> {
+        self.from_file.as_deref()
+    }
+    /// Whether the paths of --from-file are separated by NUL rather than by
+    /// newlines.
+    pub fn null(&self) -> bool {
+        self.null
+    }
+    /// Whether the first dir or file that can not be read ends the run,
+    /// rather than being passed over and reported at the end.
+    pub fn fail_fast(&self) -> bool {
+        self.fail_fast
+    }
+    /// What an entry must pass to be removed, the --keep-* rules aside:
+    /// the age check against `window` and the filters added in code.
+    pub fn selection(&self, window: TimeWindow) -> And<Age, All> {
+        Age { window, field: self.time_field }.and(All(self.filters.clone()))
+    }
+}
+
+/// Builds a `Config` in code rather than from a command line, for programs
+/// using the library. The options are those of the command line, checked
+/// the same way; a bad value or combination is reported by `build`.
+///
+/// ```no_run
+/// use rm_old::arg::ConfigBuilder;
+/// use rm_old::time::Cutoff;
+/// use std::time::Duration;
+///
+/// let config = ConfigBuilder::new()
+///     .target("/var/tmp/cache")
+///     .older_than(Cutoff::Age(Duration::from_secs(30 * 86400)))
+///     .recursive(true)
+///     .include("*.log")
+///     .dry_run(true)
+///     .build()
+///     .unwrap();
+/// ```
+pub struct ConfigBuilder {
+    config:     Config,
+    targets:    Vec<PathBuf>,
+    /// The first bad value given.
+    error:      Option<Error>,
+}
+
+impl Default for ConfigBuilder {
+    fn default() -> ConfigBuilder {
+        ConfigBuilder::new()
+    }
+}
+
+impl ConfigBuilder {
+    /// Nothing set, as an empty command line: files older than 60 days by
+    /// mtime.
+    pub fn new() -> ConfigBuilder {
+        ConfigBuilder {
+            config:     Config::new(),
+            targets:    Vec::new(),
+            error:      None,
+        }
+    }
+
+    /// Set an option the way the command line does, keeping the first error.
+    fn set(mut self, opt: &str, value: &str) -> ConfigBuilder {
+        if let Err(err) = set_option_value(opt, value, &mut self.config) {
+            self.error.get_or_insert(err);
+        }
+        self
+    }
+
+    /// A dir to search, as dir_path. Can be given more than once.
+    pub fn target<P: AsRef<Path>>(mut self, path: P) -> ConfigBuilder {
+        self.targets.push(path.as_ref().to_path_buf());
+        self
+    }
+    /// The files listed in `list` ("-" for stdin) instead of target dirs,
+    /// as --from-file; `null` as --null.
+    pub fn from_file(mut self, list: &str, null: bool) -> ConfigBuilder {
+        self.config.from_file = Some(list.to_string());
+        self.config.null = null;
+        self
+    }
+    pub fn older_than(mut self, cutoff: Cutoff) -> ConfigBuilder {
+        self.config.older_than = Some(cutoff);
+        self
+    }
+    pub fn newer_than(mut self, cutoff: Cutoff) -> ConfigBuilder {
+        self.config.newer_than = Some(cutoff);
+        self
+    }
+    pub fn time_field(mut self, field: TimeField) -> ConfigBuilder {
+        self.config.time_field = field;
+        self
+    }
+    /// The --keep-* rules.
+    pub fn policy(mut self, policy: Policy) -> ConfigBuilder {
+        self.config.policy = policy;
+        self
+    }
+    pub fn max_total_size(mut self, bytes: u64) -> ConfigBuilder {
+        self.config.max_total_size = Some(bytes);
+        self
+    }
+    pub fn min_free(mut self, watermark: Watermark) -> ConfigBuilder {
+        self.config.min_free = Some(watermark);
+        self
+    }
+    pub fn include(self, glob: &str) -> ConfigBuilder {
+        self.set("--include", glob)
+    }
+    pub fn exclude(self, glob: &str) -> ConfigBuilder {
+        self.set("--exclude", glob)
+    }
+    pub fn regex(self, regex: &str) -> ConfigBuilder {
+        self.set("--regex", regex)
+    }
+    pub fn exclude_regex(self, regex: &str) -> ConfigBuilder {
+        self.set("--exclude-regex", regex)
+    }
+    pub fn recursive(mut self, recursion: bool) -> ConfigBuilder {
+        self.config.recursion = recursion;
+        self
+    }
+    pub fn jobs(self, jobs: usize) -> ConfigBuilder {
+        self.set("--jobs", &jobs.to_string())
+    }
+    pub fn fail_fast(mut self, fail_fast: bool) -> ConfigBuilder {
+        self.config.fail_fast = fail_fast;
+        self
+    }
+    pub fn remove_dir(mut self, remove_dir: bool) -> ConfigBuilder {
+        self.config.remove_dir = remove_dir;
+        self
+    }
+    pub fn remove_empty(mut self, remove_empty: bool) -> ConfigBuilder {
+        self.config.remove_empty = remove_empty;
+        self
+    }
+    pub fn trash(mut self, trash: bool) -> ConfigBuilder {
+        self.config.trash = trash;
+        self
+    }
+    pub fn move_to(mut self, dir: &str) -> ConfigBuilder {
+        self.config.move_to = Some(dir.to_string());
+        self
+    }
+    pub fn on_conflict(mut self, conflict: Conflict) -> ConfigBuilder {
+        self.config.on_conflict = conflict;
+        self
+    }
+    pub fn archive(self, path: &str) -> ConfigBuilder {
+        self.set("--archive", path)
+    }
+    pub fn journal(mut self, path: &str) -> ConfigBuilder {
+        self.config.journal = Some(path.to_string());
+        self
+    }
+    pub fn dry_run(mut self, dry_run: bool) -> ConfigBuilder {
+        self.config.dry_run = dry_run;
+        self
+    }
+    pub fn assume_yes(mut self, assume_yes: bool) -> ConfigBuilder {
+        self.config.assume_yes = assume_yes;
+        self
+    }
+    pub fn verbose(mut self, verbose: bool) -> ConfigBuilder {
+        self.config.verbose = verbose;
+        self
+    }
+    pub fn output(mut self, format: Format) -> ConfigBuilder {
+        self.config.output = format;
+        self
+    }
+    /// A rule of your own an entry must pass, besides the age. Can be given
+    /// more than once; see `rm_old::filter`.
+    pub fn filter<F: Filter + 'static>(mut self, filter: F) -> ConfigBuilder {
+        self.config.filters.push(Arc::new(filter));
+        self
+    }
+
+    /// The `Config`, or the first bad value or combination. The targets
+    /// must be dirs, and a target or a list is needed.
+    pub fn build(self) -> Result<Config, Error> {
+        let ConfigBuilder { mut config, targets, error } = self;
+        if let Some(err) = error {
+            return Err(err);
+        }
+        for path in targets.iter() {
+            get_path(path.as_os_str(), &mut config)?;
+        }
+        if config.target_path.is_empty() && config.from_file.is_none() {
+            return Err(Error::InvalidArgument("rm-old: Input a target dir or a list of files.".to_string()));
+        }
+        config.check()?;
+        config.check_stdin()?;
+        Ok(config)
+    }
+}
+
+/// Arguments of `rm-old apply <plan.json> [-n]`.
+#[derive(Clone, Debug)]
+pub struct ApplyConfig {
+    plan:       String,
+    dry_run:    bool,
+}
+
+impl ApplyConfig {
+    /// `args` is the whole command line, "apply" being `args[1]`.
+    pub fn parse(args: &[String]) -> Result<ApplyConfig, Error> {
+        let mut plan = None;
+        let mut dry_run = false;
+
+        for arg in args[2..].iter() {
+            if "--help" == arg || "-h" == arg {
+                return Err(Error::Help(show_apply_help()));
+            } else if "-n" == arg {
+                dry_run = true;
+            } else if arg == "--output" || arg.starts_with("--output=") {
+                return Err(Error::InvalidArgument("rm-old apply: can not be used with --output.".to_string()));
+            } else if arg.starts_with('-') && arg != "-" {
+                return Err(Error::InvalidArgument(format!("rm-old apply: illegal option: {}", arg)));
+            } else if plan.is_none() {
+                plan = Some(arg.to_string());
+            } else {
+                return Err(Error::InvalidArgument(format!("rm-old apply: unexpected argument: {}", arg)));
+            }
+        }
+
+        match plan {
+            Some(plan)  => Ok(ApplyConfig { plan, dry_run }),
+            None        => Err(Error::InvalidArgument("rm-old apply: Input the plan to apply.".to_string())),
+        }
+    }
+
+    pub fn plan(&self) -> &str {
+        &self.plan
+    }
+    pub fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+}
+
+/// Arguments of `rm-old restore <journal> [--path-filter glob] [-n]`.
+#[derive(Clone, Debug)]
+pub struct RestoreConfig {
+    journal:        String,
+    path_filter:    Vec<Pattern>,
+    dry_run:        bool,
+}
+
+impl RestoreConfig {
+    /// `args` is the whole command line, "restore" being `args[1]`.
+    pub fn parse(args: &[String]) -> Result<RestoreConfig, Error> {
+        let mut journal = None;
+        let mut path_filter = Vec::new();
+        let mut dry_run = false;
+        let mut pending = false;
+
+        for arg in args[2..].iter() {
+            if pending {
+                path_filter.push(Pattern::new(arg).map_err(Error::InvalidArgument)?);
+                pending = false;
+            } else if "--help" == arg || "-h" == arg {
+                return Err(Error::Help(show_restore_help()));
+            } else if "--path-filter" == arg {
+                pending = true;
+            } else if let Some(value) = arg.strip_prefix("--path-filter=") {
+                path_filter.push(Pattern::new(value).map_err(Error::InvalidArgument)?);
+            } else if "-n" == arg {
+                dry_run = true;
+            } else if arg == "--output" || arg.starts_with("--output=") {
+                return Err(Error::InvalidArgument("rm-old restore: can not be used with --output.".to_string()));
+            } else if arg.starts_with('-') {
+                return Err(Error::InvalidArgument(format!("rm-old restore: illegal option: {}", arg)));
+            } else if journal.is_none() {
+                journal = Some(arg.to_string());
+            } else {
+                return Err(Error::InvalidArgument(format!("rm-old restore: unexpected argument: {}", arg)));
+            }
+        }
+
+        if pending {
+            return Err(Error::InvalidArgument("rm-old restore --path-filter: Input value after --path-filter.".to_string()));
+        }
+        match journal {
+            Some(journal)   => Ok(RestoreConfig { journal, path_filter, dry_run }),
+            None            => Err(Error::InvalidArgument("rm-old restore: Input the journal to restore from.".to_string())),
+        }
+    }
+
+    pub fn journal(&self) -> &str {
+        &self.journal
+    }
+    pub fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+    /// Whether the file originally at `path` is to be restored: all files
+    /// without --path-filter, else those matching any of the globs.
+    pub fn selects(&self, path: &Path) -> bool {
+        let path = path.to_string_lossy();
+        self.path_filter.is_empty() || self.path_filter.iter().any(|pattern| pattern.matches(&path))
+    }
 }
 
-fn get_option(arg: &String, config: &mut Config) -> Result<(), String> {
-    for c in arg.as_str()[1..].chars() {
+fn get_option(arg: &str, config: &mut Config) -> Result<(), Error> {
+    for c in arg[1..].chars() {
         match c {
             '-' => {
                 match analyze_long_option(arg, config) {
                     Ok(_) => break,
-                    Err(err_msg) => return Err(format!("{}", err_msg)),
+                    Err(err) => return Err(err),
                 }
             },
             'd' => {
-                config.change_days = true;
+                config.pending_opt = Some("-d".to_string());
             },
             'r' => {
                 config.recursion = true;
@@ -157,7 +797,7 @@ fn get_option(arg: &String, config: &mut Config) -> Result<(), String> {
                 config.dry_run = true;
             }
             _ => {
-                return Err(format!("rm-old: illegal option: {}", c));
+                return Err(Error::InvalidArgument(format!("rm-old: illegal option: {}", c)));
             },
         }
     }
@@ -165,50 +805,191 @@ fn get_option(arg: &String, config: &mut Config) -> Result<(), String> {
     Ok(())
 }
 
-fn analyze_long_option(arg: &String, config: &mut Config) -> Result<(), String>{
-    if arg == "--remove-dir" {
-        config.remove_dir = true;
-        Ok(())
-    } else if arg == "--remove-empty"{
-        config.remove_empty = true;
-        Ok(())
-    } else {
-        Err(format!("rm-old: illegal option: {}", arg))
+fn analyze_long_option(arg: &str, config: &mut Config) -> Result<(), Error>{
+    // "--name=value" is accepted as well as "--name value".
+    let (name, value) = match arg.find('=') {
+        Some(pos)   => (&arg[..pos], Some(&arg[pos + 1..])),
+        None        => (arg, None),
+    };
+
+    match name {
+        "--remove-dir"      => config.remove_dir = true,
+        "--remove-empty"    => config.remove_empty = true,
+        "--trash"           => config.trash = true,
+        "--print0"          => config.print0 = true,
+        "--null"            => config.null = true,
+        "--fail-fast"       => config.fail_fast = true,
+        "--time-field" | "--older-than" | "--newer-than" | "--keep-last"
+            | "--keep-daily" | "--keep-weekly" | "--keep-monthly" | "--keep-yearly"
+            | "--max-total-size" | "--min-free" | "--include" | "--exclude"
+            | "--regex" | "--exclude-regex" | "--move-to" | "--on-conflict"
+            | "--archive" | "--journal" | "--output" | "--from-file" | "--jobs" => {
+            match value {
+                Some(v) => set_option_value(name, v, config)?,
+                None    => config.pending_opt = Some(name.to_string()),
+            }
+            return Ok(());
+        },
+        _ => return Err(Error::InvalidArgument(format!("rm-old: illegal option: {}", arg))),
     }
+
+    if value.is_some() {
+        return Err(Error::InvalidArgument(format!("rm-old {}: does not take a value.", name)));
+    }
+    Ok(())
 }
 
-fn get_path(arg: &String, config: &mut Config) -> Result<(), String> {
-    let path = Path::new(arg);
-    if path.exists() && path.is_dir(){
-        if path.has_root() {
-            config.target_path.push(arg.clone());
-        } else {
-            if arg.chars().last().unwrap() == '/' {
-                let mut arg_clone = arg.clone();
-                arg_clone.remove(arg.len()-1);
-                config.target_path.push(format!("./{}", arg_clone));
-            } else {
-                config.target_path.push(format!("./{}", arg));
+fn set_option_value(opt: &str, value: &str, config: &mut Config) -> Result<(), Error> {
+    match opt {
+        "-d"            => {
+            // A bare number is a count of days.
+            let age = match value.parse::<u64>() {
+                Ok(days)    => days.checked_mul(86400).map(Duration::from_secs),
+                Err(_)      => time::parse_duration(value).ok(),
+            };
+            config.older_than = match age {
+                Some(age)   => Some(Cutoff::Age(age)),
+                None        => return Err(Error::InvalidArgument(format!("rm-old -d: Illegal value: {}", value))),
+            };
+        },
+        "--older-than"  => config.older_than = Some(Cutoff::parse(value)?),
+        "--max-total-size" => config.max_total_size = Some(size::parse_size(value)?),
+        "--min-free"    => config.min_free = Some(Watermark::parse(value)?),
+        "--include"     => config.include.push(Pattern::new(value).map_err(Error::InvalidArgument)?),
+        "--exclude"     => config.exclude.push(Pattern::new(value).map_err(Error::InvalidArgument)?),
+        "--regex"       => config.regex.push(NameRegex::new(value).map_err(Error::InvalidArgument)?),
+        "--exclude-regex" => config.exclude_regex.push(NameRegex::new(value).map_err(Error::InvalidArgument)?),
+        "--move-to"     => config.move_to = Some(value.to_string()),
+        "--on-conflict" => config.on_conflict = Conflict::parse(value)?,
+        "--journal"     => config.journal = Some(value.to_string()),
+        "--output"      => config.output = Format::parse(value)?,
+        "--from-file"   => config.from_file = Some(value.to_string()),
+        "--jobs"        => {
+            config.jobs = match value.parse::<usize>() {
+                Ok(jobs) if jobs > 0    => Some(jobs),
+                _                       => return Err(Error::InvalidArgument(format!("rm-old --jobs: Illegal value: {}", value))),
+            };
+        },
+        "--archive"     => {
+            archive::Compression::from_path(value)?;
+            config.archive = Some(value.to_string());
+        },
+        "--newer-than"  => config.newer_than = Some(Cutoff::parse(value)?),
+        "--time-field"  => config.time_field = TimeField::parse(value)?,
+        "--keep-last" | "--keep-daily" | "--keep-weekly" | "--keep-monthly" | "--keep-yearly" => {
+            let count = match value.parse::<usize>() {
+                Ok(num) => Some(num),
+                Err(_)  => return Err(Error::InvalidArgument(format!("rm-old {}: Illegal value: {}", opt, value))),
+            };
+            match opt {
+                "--keep-last"       => config.policy.last = count,
+                "--keep-daily"      => config.policy.daily = count,
+                "--keep-weekly"     => config.policy.weekly = count,
+                "--keep-monthly"    => config.policy.monthly = count,
+                _                   => config.policy.yearly = count,
             }
-        }
+        },
+        _               => return Err(Error::InvalidArgument(format!("rm-old: illegal option: {}", opt))),
+    }
+    Ok(())
+}
+
+fn get_path(arg: &OsStr, config: &mut Config) -> Result<(), Error> {
+    let path = Path::new(arg);
+    let meta = path.metadata().map_err(|why| Error::io(path, why))?;
+    if meta.is_dir(){
+        config.target_path.push(dir_path(arg));
     } else {
-        return Err(format!("rm-old: illegal path: {}", arg));
+        return Err(Error::InvalidArgument(format!("rm-old: illegal path: {} (not a dir)", path.display())));
     }
 
     Ok(())
 }
 
+/// The form a dir is shown and joined in: relative paths start with "./"
+/// and there is no trailing "/".
+pub(crate) fn dir_path(arg: &OsStr) -> PathBuf {
+    let path: PathBuf = Path::new(arg).components().collect();
+    match path.components().next() {
+        None                                            => PathBuf::from("."),
+        Some(Component::Normal(_)) | Some(Component::ParentDir) => Path::new(".").join(path),
+        Some(_)                                         => path,
+    }
+}
+
 fn show_help() -> String {
     "rm-old: remove the old files in dir.
     -r              : recursion. target dir in dir.
+    --jobs [N]      : number of threads scanning the dirs with -r.(default is the number of CPUs)
+    --fail-fast     : stop at the first dir or file that can not be read.
     -i              : ask each file when remove.
     -y              : assume yes.
-    -d [days]       : specify duration of day.(default is 60 days)
+    -d [duration]   : remove files older than duration.(default is 60 days)
+                      a bare number is days, or units s, m, h, d, w, mo, y (e.g. 36h, 1w2d).
+    --older-than [duration|date]
+                    : same as -d, also accepts an RFC3339 date (e.g. 2026-01-01T00:00:00Z).
+    --newer-than [duration|date]
+                    : only remove files newer than this.
+    --keep-last [N] : keep the newest N files in each dir.
+    --keep-daily [N], --keep-weekly [N], --keep-monthly [N], --keep-yearly [N]
+                    : keep the newest file of each of the last N days/weeks/months/years (UTC).
+                      without -d, files not kept by --keep-* are removed regardless of age.
+    --max-total-size [size]
+                    : remove the oldest files until each target dir fits in size (e.g. 20G).
+    --min-free [percent|size]
+                    : remove the oldest files only while the filesystem has less free space than this (e.g. 10%, 50G).
+    --include [glob]: only remove files matching glob. (repeatable)
+    --exclude [glob]: never remove files matching glob, nor look into matching dirs. (repeatable)
+                      globs match the base name or the path relative to the target dir, ** matches any dirs.
+    --regex [regex] : only remove files whose path relative to the target dir matches regex. (repeatable)
+    --exclude-regex [regex]
+                    : never remove files matching regex, nor look into matching dirs. (repeatable)
     -v              : verbose.
     -n              : dry run. not a remove, only show log.
+    --output [text|json|ndjson]
+                    : json/ndjson print one record per candidate and action and a summary, and nothing else.
+    --print0        : only print the paths that would be removed, each followed by a NUL.
+    --from-file [file|-]
+                    : consider the files listed in file (one per line, - for stdin) instead of a dir_path.
+    --null          : the paths of --from-file are separated by NUL, as printed by find -print0.
     --remove-dir    : remove directory.
-    --remove-empty:
-    -h, --help  : show help.(this!)
+    --remove-empty  : remove empty directory.
+    --trash         : move to the trash (freedesktop.org) instead of removing.
+    --move-to [dir] : move into dir, keeping the path relative to the target dir, instead of removing.
+    --on-conflict [skip|overwrite|rename]
+                    : when the destination of --move-to exists.(default is skip)
+    --archive [path.tar|path.tar.gz|path.tar.zst]
+                    : pack the files into a new tar archive, check it, then remove them.
+    --journal [file]: where --trash/--move-to record the moved files for `rm-old restore`.
+                      (default is $XDG_DATA_HOME/rm-old/journal/<time>-<pid>.tsv)
+    --time-field [atime|mtime|ctime|btime]
+                    : timestamp used as the age of a file.(default is mtime)
+    -h, --help      : show help.(this!)
+    rm-old restore [journal] : put back the files recorded in a journal, see `rm-old restore -h`.
+    rm-old plan [dir_path] [option] -o [plan.json]
+                    : write the files that would be removed to plan.json instead of removing them.
+    rm-old apply [plan.json] : remove the files of a plan, see `rm-old apply -h`.
+    ".to_string()
+}
+
+fn show_apply_help() -> String {
+    "rm-old apply: remove the files listed by `rm-old plan`, without asking.
+    Usage           : rm-old apply [plan.json] [option]
+    -n              : dry run. not a remove, only show log.
+    -h, --help      : show help.(this!)
+    Files that were replaced (other device/inode), modified so that they are no longer
+    older than the cutoff of the plan, or removed are skipped.
+    ".to_string()
+}
+
+fn show_restore_help() -> String {
+    "rm-old restore: put back the files moved by --trash/--move-to.
+    Usage           : rm-old restore [journal] [option]
+    --path-filter [glob]
+                    : only restore files whose original path matches glob (e.g. /data/logs/**). (repeatable)
+    -n              : dry run. not a restore, only show log.
+    -h, --help      : show help.(this!)
+    Files whose original path exists again are reported as conflicts and left where they are.
     ".to_string()
 }
 
@@ -245,6 +1026,27 @@ mod test{
                                                     vec!["rm-old".to_string(), "--remove-dir".to_string()],
                                                     vec!["rm-old".to_string(), "--remove-empty".to_string()],
                                                     vec!["rm-old".to_string(), "--remove-empty".to_string()],
+                                                    vec!["rm-old".to_string(), "--trash".to_string(), "--remove-dir".to_string()],
+                                                    vec!["rm-old".to_string(), "--move-to".to_string(), "/tmp".to_string(), "--on-conflict=rename".to_string()],
+                                                    vec!["rm-old".to_string(), "--archive".to_string(), "/tmp/old.tar".to_string()],
+                                                    vec!["rm-old".to_string(), "--trash".to_string(), "--journal=/tmp/run.tsv".to_string()],
+                                                    vec!["rm-old".to_string(), "--output".to_string(), "ndjson".to_string()],
+                                                    vec!["rm-old".to_string(), "-r".to_string(), "--print0".to_string()],
+                                                    vec!["rm-old".to_string(), "-r".to_string(), "--jobs".to_string(), "4".to_string()],
+                                                    vec!["rm-old".to_string(), "-r".to_string(), "--fail-fast".to_string()],
+                                                    vec!["rm-old".to_string(), "--from-file".to_string(), "-".to_string(), "--null".to_string(), "-y".to_string()],
+                                                    vec!["rm-old".to_string(), "--from-file=-".to_string(), "--print0".to_string()],
+                                                    vec!["rm-old".to_string(), "--from-file=list.txt".to_string(), "--trash".to_string()],
+                                                    vec!["rm-old".to_string(), "--time-field".to_string(), "atime".to_string()],
+                                                    vec!["rm-old".to_string(), "--time-field=ctime".to_string(), "-d".to_string(), "3".to_string()],
+                                                    vec!["rm-old".to_string(), "-d".to_string(), "1w2d".to_string()],
+                                                    vec!["rm-old".to_string(), "--keep-last".to_string(), "10".to_string(), "-d".to_string(), "30".to_string()],
+                                                    vec!["rm-old".to_string(), "--max-total-size".to_string(), "20GiB".to_string(), "-r".to_string()],
+                                                    vec!["rm-old".to_string(), "--min-free".to_string(), "10%".to_string()],
+                                                    vec!["rm-old".to_string(), "--min-free=50G".to_string(), "-d".to_string(), "7".to_string()],
+                                                    vec!["rm-old".to_string(), "--include".to_string(), "*.log".to_string(), "--include=**/*.gz".to_string(), "--exclude".to_string(), "keep".to_string()],
+                                                    vec!["rm-old".to_string(), "--keep-daily=7".to_string(), "--keep-weekly=4".to_string(), "--keep-monthly=12".to_string(), "--keep-yearly=3".to_string()],
+                                                    vec!["rm-old".to_string(), "--older-than".to_string(), "2026-01-01".to_string(), "--newer-than=1y".to_string()],
         ];
 
         let invalid_args: Vec<Vec<String>> = vec![  // After "-d" is not number.
@@ -255,13 +1057,155 @@ mod test{
                                                     vec!["rm-old".to_string(), "-driyvn".to_string()],
                                                     // not supported.
                                                     vec!["rm-old".to_string(), "--get-list".to_string()],
+                                                    // bad duration unit.
+                                                    vec!["rm-old".to_string(), "-d".to_string(), "3x".to_string()],
+                                                    // bad date.
+                                                    vec!["rm-old".to_string(), "--older-than".to_string(), "2026-02-30".to_string()],
+                                                    // bad size.
+                                                    vec!["rm-old".to_string(), "--max-total-size=20X".to_string()],
+                                                    // size quota in directory mode.
+                                                    vec!["rm-old".to_string(), "--max-total-size=1G".to_string(), "--remove-dir".to_string()],
+                                                    // bad watermark.
+                                                    vec!["rm-old".to_string(), "--min-free=150%".to_string()],
+                                                    // bad glob.
+                                                    vec!["rm-old".to_string(), "--include=[a-".to_string()],
+                                                    // two actions.
+                                                    vec!["rm-old".to_string(), "--move-to=/tmp".to_string(), "--trash".to_string()],
+                                                    // unknown conflict policy.
+                                                    vec!["rm-old".to_string(), "--on-conflict=merge".to_string()],
+                                                    // unknown archive format, or files picked one by one.
+                                                    vec!["rm-old".to_string(), "--archive=/tmp/old.zip".to_string()],
+                                                    vec!["rm-old".to_string(), "--archive=/tmp/old.tar".to_string(), "-i".to_string()],
+                                                    // nothing to journal.
+                                                    vec!["rm-old".to_string(), "--journal=/tmp/run.tsv".to_string()],
+                                                    // unknown output format.
+                                                    vec!["rm-old".to_string(), "--output=xml".to_string()],
+                                                    // listing only, yet asked to act.
+                                                    vec!["rm-old".to_string(), "--print0".to_string(), "--trash".to_string()],
+                                                    // a list and a dir, or no list.
+                                                    vec!["rm-old".to_string(), "test_dir".to_string(), "--from-file=list.txt".to_string()],
+                                                    vec!["rm-old".to_string(), "--null".to_string()],
+                                                    // prompts would read the list.
+                                                    vec!["rm-old".to_string(), "--from-file".to_string(), "-".to_string()],
+                                                    // no threads.
+                                                    vec!["rm-old".to_string(), "-r".to_string(), "--jobs=0".to_string()],
+                                                    // negative count.
+                                                    vec!["rm-old".to_string(), "--keep-last=-1".to_string()],
+                                                    // unknown time field.
+                                                    vec!["rm-old".to_string(), "--time-field".to_string(), "xtime".to_string()],
+                                                    // After "--time-field" not exist.
+                                                    vec!["rm-old".to_string(), "--time-field".to_string()],
+                                                    // flag with value.
+                                                    vec!["rm-old".to_string(), "--remove-dir=yes".to_string()],
         ];
 
         for arg in correct_args.iter() {
-            assert!(Config::parse_config(&arg).is_ok());
+            assert!(Config::parse_config(arg).is_ok());
         }
         for arg in invalid_args.iter() {
-            assert!(Config::parse_config(&arg).is_err());
+            assert!(Config::parse_config(arg).is_err());
+        }
+
+        assert!(matches!(Config::parse_config(&["rm-old", "-h"]), Err(Error::Help(_))));
+        assert!(matches!(Config::parse_config(&["rm-old", "--keep-last=-1"]), Err(Error::InvalidArgument(_))));
+        assert!(matches!(Config::parse_config(&["rm-old", "not/exists/path"]), Err(Error::Io { .. })));
+        assert!(matches!(Config::parse_config(&["rm-old", "Cargo.toml"]), Err(Error::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn test_config_builder() {
+        let config = ConfigBuilder::new()
+            .target("src")
+            .older_than(Cutoff::Age(Duration::from_secs(3600)))
+            .recursive(true)
+            .include("*.rs")
+            .jobs(2)
+            .dry_run(true)
+            .build()
+            .unwrap();
+        let parsed = Config::parse_config(&["rm-old", "src", "-d", "1h", "-r", "--include", "*.rs", "--jobs", "2", "-n"]).unwrap();
+        assert_eq!(parsed.get_target_path(), config.get_target_path());
+        assert_eq!(parsed.older_than(), config.older_than());
+        assert!(config.recursion() && config.dry_run());
+        assert_eq!(2, config.jobs());
+
+        // Bad values and combinations are found by build.
+        assert!(matches!(ConfigBuilder::new().target("src").include("[a").build(), Err(Error::InvalidArgument(_))));
+        assert!(matches!(ConfigBuilder::new().target("src").jobs(0).build(), Err(Error::InvalidArgument(_))));
+        assert!(matches!(ConfigBuilder::new().target("src").trash(true).move_to("/tmp").build(), Err(Error::InvalidArgument(_))));
+        assert!(matches!(ConfigBuilder::new().target("not/exists/path").build(), Err(Error::Io { .. })));
+        assert!(matches!(ConfigBuilder::new().build(), Err(Error::InvalidArgument(_))));
+        assert!(ConfigBuilder::new().from_file("-", true).build().is_err());
+        assert!(ConfigBuilder::new().from_file("-", true).assume_yes(true).build().is_ok());
+    }
+
+    #[test]
+    fn test_time_window() {
+        let now = SystemTime::now();
+        let config = Config::parse_config(&["rm-old".to_string(), "-d".to_string(), "36h".to_string()]).unwrap();
+        let window = config.time_window(now).unwrap();
+        assert_eq!(now - Duration::from_secs(36 * 3600), window.older);
+        assert!(window.newer.is_none());
+
+        let config = Config::parse_config(&["rm-old".to_string(), "--older-than=2026-01-01".to_string(), "--newer-than=2025-01-01T00:00:00Z".to_string()]).unwrap();
+        let window = config.time_window(now).unwrap();
+        assert_eq!(time::parse_datetime("2026-01-01").unwrap(), window.older);
+        assert_eq!(Some(time::parse_datetime("2025-01-01").unwrap()), window.newer);
+    }
+
+    #[test]
+    fn test_restore_config() {
+        let args = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+
+        let config = RestoreConfig::parse(&args(&["rm-old", "restore", "run.tsv"])).unwrap();
+        assert_eq!("run.tsv", config.journal());
+        assert!(!config.dry_run());
+        assert!(config.selects(Path::new("/any/path")));
+
+        let config = RestoreConfig::parse(&args(&["rm-old", "restore", "-n", "run.tsv", "--path-filter", "/data/**", "--path-filter=/x/*.log"])).unwrap();
+        assert!(config.dry_run());
+        assert!(config.selects(Path::new("/data/a/b")));
+        assert!(config.selects(Path::new("/x/a.log")));
+        assert!(!config.selects(Path::new("/x/y/a.log")));
+
+        assert!(RestoreConfig::parse(&args(&["rm-old", "restore"])).is_err());
+        assert!(RestoreConfig::parse(&args(&["rm-old", "restore", "a", "b"])).is_err());
+        assert!(RestoreConfig::parse(&args(&["rm-old", "restore", "a", "--path-filter"])).is_err());
+        assert!(RestoreConfig::parse(&args(&["rm-old", "restore", "a", "-r"])).is_err());
+        assert!(RestoreConfig::parse(&args(&["rm-old", "restore", "a", "--output=json"])).unwrap_err().to_string().contains("--output"));
+    }
+
+    #[test]
+    fn test_plan_config() {
+        let args = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+
+        let (config, output) = Config::parse_plan(&args(&["rm-old", "plan", "-r", "-o", "plan.json", "-d", "3"])).unwrap();
+        assert_eq!("plan.json", output);
+        assert!(config.recursion());
+        assert!(Config::parse_plan(&args(&["rm-old", "plan", "-r"])).is_err());
+        assert!(Config::parse_plan(&args(&["rm-old", "plan", "-o"])).is_err());
+        assert!(Config::parse_plan(&args(&["rm-old", "plan", "-o", "p.json", "--trash"])).is_err());
+        assert!(Config::parse_plan(&args(&["rm-old", "plan", "-o", "p.json", "--output", "json"])).is_err());
+        let (config, _) = Config::parse_plan(&args(&["rm-old", "plan", "--from-file", "-", "-o", "p.json"])).unwrap();
+        assert_eq!(Some("-"), config.from_file());
+
+        let config = ApplyConfig::parse(&args(&["rm-old", "apply", "plan.json", "-n"])).unwrap();
+        assert_eq!("plan.json", config.plan());
+        assert!(config.dry_run());
+        assert!(ApplyConfig::parse(&args(&["rm-old", "apply"])).is_err());
+        assert!(ApplyConfig::parse(&args(&["rm-old", "apply", "a", "-y"])).is_err());
+        assert!(ApplyConfig::parse(&args(&["rm-old", "apply", "a", "--output", "ndjson"])).unwrap_err().to_string().contains("--output"));
+    }
+
+    #[test]
+    fn test_time_field() {
+        let config = Config::parse_config(&["rm-old".to_string()]).unwrap();
+        assert_eq!(TimeField::Mtime, config.time_field());
+
+        for name in ["atime", "mtime", "ctime", "btime"].iter() {
+            let config = Config::parse_config(&["rm-old".to_string(), format!("--time-field={}", name)]).unwrap();
+            assert_eq!(*name, config.time_field().name());
         }
+        assert!(matches!(TimeField::parse("birth"), Err(Error::InvalidArgument(_))));
     }
 }
\ No newline at end of file
@@ -2,154 +2,672 @@
 Usage       :rm-old [dir_path] [option]
 Options
 -r              : recursion. target dir in dir.
+--jobs [N]      : number of threads scanning the dirs with -r.(default is the number of CPUs)
+--fail-fast     : stop at the first dir or file that can not be read.
 -i              : ask each file when remove.
 -y              : assume yes.
--d [days]       : specify duration of day.(default is 60 days)
+-d [duration]   : remove files older than duration.(default is 60 days)
+--older-than [duration|date]
+                : same as -d, also accepts an RFC3339 date.
+--newer-than [duration|date]
+                : only remove files newer than this.
+--keep-last [N] : keep the newest N files in each dir.
+--keep-daily [N], --keep-weekly [N], --keep-monthly [N], --keep-yearly [N]
+                : keep the newest file of each of the last N days/weeks/months/years.
+--max-total-size [size]
+                : remove the oldest files until each target dir fits in size.
+--min-free [percent|size]
+                : remove the oldest files only while free space is below this.
+--include [glob]: only remove files matching glob. (repeatable)
+--exclude [glob]: never remove files matching glob, nor look into matching dirs. (repeatable)
+--regex [regex] : only remove files whose relative path matches regex. (repeatable)
+--exclude-regex [regex]
+                : never remove files matching regex, nor look into matching dirs. (repeatable)
 -v              : verbose.
 -n              : dry run. not a remove, only show log.
+--output [text|json|ndjson]
+                : json/ndjson print one record per candidate and action and a summary.
+--print0        : only print the paths that would be removed, each followed by a NUL.
+--from-file [file|-]
+                : consider the files listed in file (- for stdin) instead of a dir_path.
+--null          : the paths of --from-file are separated by NUL.
 --remove-dir    : remove directory.
 --remove-empty  : remove empty dir.
+--trash         : move to the trash instead of removing.
+--move-to [dir] : move into dir, keeping the relative path, instead of removing.
+--on-conflict [skip|overwrite|rename]
+                : when the destination of --move-to exists.(default is skip)
+--archive [path.tar|path.tar.gz|path.tar.zst]
+                : pack the files into a new tar archive, check it, then remove them.
+--journal [file]: where --trash/--move-to record the moved files.
+--time-field [atime|mtime|ctime|btime]
+                : timestamp used as the age of a file.(default is mtime)
 -h, --help      : show help.
+
+Usage       :rm-old restore [journal] [option]
+--path-filter [glob]
+                : only restore files whose original path matches glob. (repeatable)
+-n              : dry run. not a restore, only show log.
+
+Usage       :rm-old plan [dir_path] [option] -o [plan.json]
+Usage       :rm-old apply [plan.json] [-n]
 */
 
 extern crate rm_old;
 
-use rm_old::{arg::Config, fs::Dir};
+use rm_old::action::{self, Action, Failure, Target};
+use rm_old::{arg::{ApplyConfig, Config, RestoreConfig}, disk::{self, Watermark}, fs::{self as rm_fs, Dir, Entry}, size};
+use rm_old::journal::{self, Outcome};
+use rm_old::pathenc;
+use rm_old::plan::Plan;
+use rm_old::report::{self, Format, Reporter, Summary};
+use rm_old::scan::Scanner;
+use rm_old::time;
+use rm_old::unlink::{self, RemoveError};
+use rm_old::Error;
 
 use std::env;
-use std::path::Path;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
 use std::fs;
 use std::io::{self, Write};
+use std::process;
+use std::time::SystemTime;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    // Dir paths need not be UTF-8.
+    process::exit(run(env::args_os().collect()));
+}
+
+/// Run the command line `args`, returning the exit status.
+fn run(args: Vec<OsString>) -> i32 {
+    let text_args = || args.iter().map(|arg| arg.to_string_lossy().into_owned()).collect::<Vec<String>>();
+
+    match args.get(1).and_then(|arg| arg.to_str()) {
+        Some("restore") => return restore(&text_args()),
+        Some("plan")    => return plan(&args),
+        Some("apply")   => return apply(&text_args()),
+        _               => {},
+    }
 
     let ret_config = match Config::parse_config(&args) {
         Ok(config)    => config,
-        Err(err_msg)      => {
-            println!("{}\nUsage: rm-old [dir_path] [option]", err_msg);
-            return ;
-        },
+        Err(err)      => return usage_error(err, "rm-old [dir_path] [option]"),
     };
+    let mut report = Reporter::new(ret_config.output());
 
     ret_config.print();
 
+    if ret_config.print0() {
+        return print0(&ret_config);
+    }
+
+    // Taken before the scan, so that it is never later than the cutoff the
+    // scan used.
+    let cutoff = match ret_config.time_window(SystemTime::now()) {
+        Ok(window)      => window.older,
+        Err(err)        => return fatal(&mut report, err),
+    };
     let target_files = match Dir::get_target_files(&ret_config) {
         Ok(files)       => files,
-        Err(err_msg)    => {
-            println!("{}", err_msg);
-            return ;
+        Err(err)        => return fatal(&mut report, err),
+    };
+
+    let scan_errors = count_skipped(&target_files);
+    // Something was passed over, so the run did not do all it was asked.
+    let status = if scan_errors > 0 { SKIPPED } else { 0 };
+    if target_files.iter().all(|dir| dir.get_amount_files() == 0) {
+        if !report.is_text() {
+            list_targets(&target_files, &mut report);
+            report.emit(report::Record::Summary(Summary { scan_errors, dry_run: ret_config.dry_run(), ..Summary::default() }));
+            report.finish();
+            return status;
         }
+        if ret_config.dry_run() || ret_config.verbose() {
+            for dir in target_files.iter().rev() {
+                dir.print_kept();
+            }
+        }
+        println!("{}", Error::NoTargets);
+        print_skipped(&target_files);
+        return status;
+    }
+
+    let result = execute_rm(&target_files, &ret_config, cutoff, &mut report);
+    if report.is_text() {
+        print_skipped(&target_files);
+    }
+    let failed = match result {
+        Ok(failed) if report.is_text()  => {
+            println!("Complete!");
+            failed
+        },
+        Ok(failed)                      => {
+            report.finish();
+            failed
+        },
+        Err(Stop::Canceled)             => {
+            report_error(&mut report, "Canceled.".to_string());
+            return 1;
+        },
+        Err(Stop::Error(err))           => {
+            report_error(&mut report, format!("{}\nNothing was removed.", err));
+            return exit_code(&err);
+        },
     };
+    if failed > 0 { 1 } else { status }
+}
+
+/// Exit status of a run that passed over dirs or files it could not read.
+const SKIPPED: i32 = 5;
+
+/// The exit status for `err`: 2 for a bad command line or ignore file, 3
+/// for an I/O error, 4 for a clock far off. Help and finding nothing to
+/// remove are not failures.
+fn exit_code(err: &Error) -> i32 {
+    match err {
+        Error::Help(_) | Error::NoTargets               => 0,
+        Error::InvalidArgument(_) | Error::IgnoreFile(_) => 2,
+        Error::Io { .. }                                => 3,
+        Error::ClockSkew(_)                             => 4,
+    }
+}
+
+/// Print the error of a command line that could not be parsed, or the help
+/// asked for.
+fn usage_error(err: Error, usage: &str) -> i32 {
+    match err {
+        Error::Help(ref help)   => println!("{}", help),
+        Error::Io { .. }        => println!("{}", err),
+        _                       => println!("{}\nUsage: {}", err, usage),
+    }
+    exit_code(&err)
+}
+
+/// List the paths of the targets for `xargs -0` as they are found, each
+/// followed by a NUL. The paths are written as they are, UTF-8 or not.
+/// Errors go to stderr.
+fn print0(config: &Config) -> i32 {
+    let mut out = io::stdout().lock();
+    let mut status = 0;
+    for candidate in Scanner::new(config).iter() {
+        match candidate {
+            Ok(candidate)   => {
+                if out.write_all(&pathenc::path_bytes(&candidate.path)).and_then(|_| out.write_all(b"\0")).is_err() {
+                    return 1;
+                }
+            },
+            Err(err) if config.fail_fast()  => {
+                eprintln!("{}", err);
+                return exit_code(&err);
+            },
+            Err(err)        => {
+                eprintln!("{}", err);
+                status = SKIPPED;
+            },
+        }
+    }
+    let _ = out.flush();
+    status
+}
+
+/// Report an error that ends the run, returning the exit status.
+fn fatal(report: &mut Reporter, err: Error) -> i32 {
+    report_error(report, err.to_string());
+    exit_code(&err)
+}
+
+fn report_error(report: &mut Reporter, err_msg: String) {
+    if report.is_text() {
+        println!("{}", err_msg);
+    } else {
+        report.emit(report::Record::Error { message: err_msg });
+        report.finish();
+    }
+}
 
-    if target_files.is_empty() {
-        println!("Target files not exists!");
+/// Number of dirs and files the scan passed over.
+fn count_skipped(target_dirs: &[Dir]) -> u64 {
+    target_dirs.iter().map(|dir| dir.skipped.len() as u64).sum()
+}
+
+/// List the dirs and files the scan passed over, with why.
+fn print_skipped(target_dirs: &[Dir]) {
+    let count = count_skipped(target_dirs);
+    if count == 0 {
         return ;
     }
+    println!("skipped: {} paths that could not be read", count);
+    for skipped in target_dirs.iter().rev().flat_map(|dir| dir.skipped.iter()) {
+        println!("  {}: {}", skipped.path.display(), skipped.reason());
+    }
+}
+
+/// Emit a record for every candidate, kept file and path the scan passed
+/// over.
+fn list_targets(target_dirs: &[Dir], report: &mut Reporter) {
+    let now = SystemTime::now();
+    for dir in target_dirs.iter().rev() {
+        for skipped in dir.skipped.iter() {
+            report.emit(report::Record::ScanError {
+                path:       skipped.path.display().to_string(),
+                error:      skipped.reason(),
+            });
+        }
+        for f in dir.get_files().iter() {
+            report.emit(report::Record::Candidate {
+                path:       dir.get_path(&f.name).display().to_string(),
+                size:       f.size,
+                time:       time::format_datetime(f.time),
+                age_secs:   age_secs(now, f.time),
+            });
+        }
+        for kept in dir.kept.iter() {
+            report.emit(report::Record::Kept {
+                path:       dir.get_path(&kept.entry.name).display().to_string(),
+                reason:     kept.reason.clone(),
+            });
+        }
+    }
+}
+
+/// Why `execute_rm` acted on none of the targets.
+enum Stop {
+    Canceled,
+    /// The action could not be set up or prepared.
+    Error(Error),
+}
 
-    match execute_rm(&target_files, &ret_config) {
-        Ok(_)           => println!("Complete!"),
-        Err(err_msg)    => println!("{}", err_msg),
+impl From<Error> for Stop {
+    fn from(err: Error) -> Stop {
+        Stop::Error(err)
     }
 }
 
-fn execute_rm(target_dirs: &Vec<Dir>, config: &Config) -> Result<(), String> {
+/// Act on the targets of `target_dirs`, returning how many failed.
+fn execute_rm(target_dirs: &[Dir], config: &Config, cutoff: SystemTime, report: &mut Reporter) -> Result<u64, Stop> {
+    let text = report.is_text();
     let mut amount_target = 0;
+    let mut amount_size = 0;
     for dir in target_dirs.iter().rev() {
-        dir.print();
-        amount_target = amount_target + dir.get_amount_files();
+        if text && dir.get_amount_files() > 0 {
+            dir.print();
+        }
+        if text && (config.dry_run() || config.verbose()) {
+            dir.print_kept();
+        }
+        amount_target += dir.get_amount_files();
+        amount_size += dir.get_amount_size();
+    }
+    if !text {
+        list_targets(target_dirs, report);
+    } else if config.remove_dir() {
+        println!("target files: {}", amount_target);
+    } else {
+        println!("target files: {} ({})", amount_target, size::format_size(amount_size));
     }
 
-    println!("target files: {}", amount_target);
-
-    match interaction("Remove the above files. Ok? [Y/n]: ", config.assume_yes()) {
+    match interaction("Remove the above files. Ok? [Y/n]: ", config.assume_yes(), text) {
         Ok(_)   => {},
-        Err(_)  => {return Err("Canceled.".to_string());}
+        Err(_)  => {return Err(Stop::Canceled);}
     }
 
-    for dir in target_dirs.iter().rev() {
-        println!("{}/ :", dir.get_parent_path());
-        for f in dir.get_files().iter() {
-            match remove_target(&format!("{}/{}", dir.get_parent_path(), f), config) {
-                Ok(_)   => {},
-                Err(err_msg)  => {
-                    println!("{} {}", err_msg, f);
+    // With --min-free the oldest files go first, so that removal can stop as
+    // soon as the watermark is restored.
+    let mut work: Vec<(&Dir, &Entry)> = target_dirs.iter().rev()
+        .flat_map(|dir| dir.get_files().iter().map(move |f| (dir, f)))
+        .collect();
+    if config.min_free().is_some() {
+        work.sort_by_key(|(_, f)| f.time);
+    }
+    let targets: Vec<Target> = work.iter()
+        .map(|(dir, f)| Target {
+            path:       dir.get_path(&f.name),
+            root:       dir.get_target_path().to_path_buf(),
+            rel_path:   dir.get_rel_path(&f.name),
+            entry:      (*f).clone(),
+        })
+        .collect();
+
+    // Nothing is removed unless the whole archive was written and checked.
+    let mut action = action::from_config(config, cutoff)?;
+    action.prepare(&targets)?;
+    if text {
+        if let Some(line) = action.describe_prepare(&targets) {
+            println!("{}\n", line);
+        }
+    } else if let Some(archive_path) = action.archive() {
+        report.emit(report::Record::Archive { path: archive_path.display().to_string(), files: targets.len() });
+    }
+
+    let mut summary = Summary {
+        candidates:         amount_target,
+        candidate_bytes:    amount_size,
+        scan_errors:        count_skipped(target_dirs),
+        dry_run:            action.dry_run(),
+        ..Summary::default()
+    };
+    let now = SystemTime::now();
+    let mut restored = 0;
+    let mut last_dir: Option<&Path> = None;
+    for ((dir, f), target) in work.into_iter().zip(targets.iter()) {
+        let path = target.path.display().to_string();
+        let mut result = None;
+        if let Some(watermark) = config.min_free() {
+            match watermark_restored(dir.get_parent_path(), watermark, action.unfreed_bytes(f.dev)) {
+                Ok(true)            => {
+                    // Summed up below rather than listed.
+                    restored += 1;
+                    summary.skipped += 1;
+                    report.emit(action_record(&*action, path, f, now, Err(&Failure::Skipped("free space is restored".to_string()))));
                     continue;
                 },
+                Ok(false)           => {},
+                Err(err)            => result = Some(Err(Failure::Failed("Statvfs", err.into()))),
             }
         }
 
-        if fs::read_dir(dir.get_parent_path()).unwrap().next().is_none(){
-            if !config.dry_run() && config.remove_empty() {
-                match fs::remove_dir(dir.get_parent_path()) {
-                    Ok(()) => println!("Removed: {}", dir.get_parent_path()),
+        if text && result.is_none() && last_dir != Some(dir.get_parent_path()) {
+            if last_dir.is_some() {
+                println!();
+            }
+            println!("{}/ :", dir.get_parent_path().display());
+            last_dir = Some(dir.get_parent_path());
+        }
+        let result = match result {
+            Some(result)    => result,
+            None            => remove_target(&mut *action, target, config),
+        };
+
+        match result {
+            Ok(ref dest)    => {
+                summary.done += 1;
+                summary.reclaimed_bytes += f.size;
+                report.emit(action_record(&*action, path, f, now, Ok(dest.as_deref())));
+            },
+            Err(ref failure) => {
+                match failure {
+                    Failure::Failed(_, _)   => summary.failed += 1,
+                    _                       => summary.skipped += 1,
+                }
+                if text {
+                    println!("{} {}", failure, f.display());
+                }
+                report.emit(action_record(&*action, path, f, now, Err(failure)));
+            },
+        }
+    }
+    if text && last_dir.is_some() {
+        println!();
+    }
+
+    for dir in target_dirs.iter().rev() {
+        if dir.get_amount_files() == 0 || !config.remove_empty() {
+            continue;
+        }
+        if fs::read_dir(dir.get_parent_path()).is_ok_and(|mut entries| entries.next().is_none()){
+            let time = fs::symlink_metadata(dir.get_parent_path()).ok()
+                .and_then(|meta| rm_fs::get_file_time(&meta, config.time_field(), dir.get_parent_path()).ok());
+            let result = action.remove_empty_dir(dir.get_parent_path());
+            if result.is_err() {
+                summary.failed += 1;
+            }
+            if text {
+                match result {
+                    Ok(()) => println!("Removed: {}", dir.get_parent_path().display()),
                     Err(_) => println!("Fatal Error."),
                 }
-            } else if config.dry_run() && config.remove_empty() {
-                println!("Removed: {}", dir.get_parent_path());
+            } else {
+                report.emit(report::Record::Action {
+                    path:           dir.get_parent_path().display().to_string(),
+                    size:           0,
+                    age_secs:       time.map_or(0, |time| age_secs(now, time)),
+                    action:         "remove",
+                    result:         if result.is_ok() { "ok" } else { "failed" },
+                    destination:    None,
+                    error:          result.err().map(|why| why.to_string()),
+                    dry_run:        action.dry_run(),
+                });
             }
         }
-        println!("");
     }
-    Ok(())
+
+    summary.journal = action.journal().map(|journal| journal.display().to_string());
+    let failed = summary.failed;
+    if !text {
+        report.emit(report::Record::Summary(summary));
+        return Ok(failed);
+    }
+    if restored > 0 {
+        println!("skipped: {} files, free space is restored.", restored);
+    }
+    if !config.remove_dir() {
+        println!("reclaimed: {} ({} bytes)", size::format_size(summary.reclaimed_bytes), summary.reclaimed_bytes);
+    }
+    if let Some(journal) = summary.journal.as_ref() {
+        println!("journal: {}", journal);
+    }
+    Ok(failed)
+}
+
+/// `rm-old plan`: write what a run would remove to a file to be reviewed.
+fn plan(args: &[OsString]) -> i32 {
+    let (config, output) = match Config::parse_plan(args) {
+        Ok(parsed)      => parsed,
+        Err(err)        => return usage_error(err, "rm-old plan [dir_path] [option] -o [plan.json]"),
+    };
+    let dirs = match Dir::get_target_files(&config) {
+        Ok(dirs)        => dirs,
+        Err(err)        => {
+            println!("{}", err);
+            return exit_code(&err);
+        },
+    };
+    let status = if count_skipped(&dirs) > 0 { SKIPPED } else { 0 };
+    let result = Plan::new(&dirs, &config, SystemTime::now())
+        .and_then(|plan| plan.write(&output).map(|_| plan));
+    match result {
+        // The summary would spoil a plan written to stdout.
+        Ok(_) if output == "-"  => {
+            for skipped in dirs.iter().rev().flat_map(|dir| dir.skipped.iter()) {
+                eprintln!("{}", skipped.error);
+            }
+            status
+        },
+        Ok(plan)                => {
+            println!("planned: {} files ({}) -> {}", plan.entries.len(), size::format_size(plan.total_size()), output);
+            print_skipped(&dirs);
+            status
+        },
+        Err(err)                => {
+            println!("{}", err);
+            exit_code(&err)
+        },
+    }
 }
 
-fn remove_target(file_path: &String, config: &Config) -> Result<(), String>{
+/// `rm-old apply`: remove the entries of a plan that are still the same
+/// files and still old enough.
+fn apply(args: &[String]) -> i32 {
+    let config = match ApplyConfig::parse(args) {
+        Ok(config)      => config,
+        Err(err)        => return usage_error(err, "rm-old apply [plan.json] [-n]"),
+    };
+    let plan = match Plan::read(config.plan()) {
+        Ok(plan)        => plan,
+        Err(err)        => {
+            println!("{}", err);
+            return exit_code(&err);
+        },
+    };
 
-    if config.do_intr() {
-        println!("    {:?}", Path::new(file_path).file_name().unwrap());
-        match interaction("Remove This file? [Y/n]: ", config.assume_yes()) {
-            Ok(_)   => {},
-            Err(_)  => {return Err("Canceled:".to_string());}
+    let (mut removed, mut skipped, mut reclaimed) = (0, 0, 0);
+    for entry in plan.entries.iter() {
+        let expect = match plan.verify(entry) {
+            Ok(expect)      => expect,
+            Err(reason)     => {
+                println!("Skipped ({}): {}", reason, entry.display());
+                skipped += 1;
+                continue;
+            },
+        };
+        if !config.dry_run() {
+            let (root, rel_path) = match entry.root_and_rel_path() {
+                Some(paths)     => paths,
+                None            => {
+                    println!("Skipped (bad path): {}", entry.display());
+                    skipped += 1;
+                    continue;
+                },
+            };
+            if let Err(why) = unlink::remove(&root, &rel_path, &expect) {
+                match why {
+                    RemoveError::Changed(why)   => println!("Skipped ({}): {}", why, entry.display()),
+                    RemoveError::Io(why)        => println!("Remove failed ({}): {}", why, entry.display()),
+                }
+                skipped += 1;
+                continue;
+            }
         }
+        println!("Removed: {}", entry.display());
+        removed += 1;
+        reclaimed += entry.size;
     }
-    if !config.dry_run() {
-        if !config.remove_dir() {
-            match fs::remove_file(file_path) {
-                Ok(_)   => {println!("Removed: {:?}", Path::new(file_path).file_name().unwrap())},
-                Err(_)  => {return Err("Remove failed:".to_string());},
-            }
-        } else {
-            match fs::remove_dir_all(file_path) {
-                Ok(_)   => {println!("Removed: {:?}", Path::new(file_path).file_name().unwrap())},
-                Err(_)  => {return Err("Remove failed:".to_string());},
-            }
+    println!("removed: {}, skipped: {}", removed, skipped);
+    if !plan.remove_dir {
+        println!("reclaimed: {} ({} bytes)", size::format_size(reclaimed), reclaimed);
+    }
+    if skipped > 0 { 1 } else { 0 }
+}
+
+/// `rm-old restore`: move the files of a journal back, newest first.
+fn restore(args: &[String]) -> i32 {
+    let config = match RestoreConfig::parse(args) {
+        Ok(config)      => config,
+        Err(err)        => return usage_error(err, "rm-old restore [journal] [option]"),
+    };
+    let records = match journal::read(Path::new(config.journal())) {
+        Ok(records)     => records,
+        Err(err)        => {
+            println!("{}", err);
+            return exit_code(&err);
+        },
+    };
+
+    let (mut restored, mut conflicts, mut missing, mut failed) = (0, 0, 0, 0);
+    for record in records.iter().rev().filter(|record| config.selects(&record.original)) {
+        match journal::restore(record, config.dry_run()) {
+            Ok(Outcome::Restored)   => {
+                println!("Restored: {} -> {}", record.destination.display(), record.original.display());
+                restored += 1;
+            },
+            Ok(Outcome::Conflict)   => {
+                println!("Conflict: {} exists, left at {}", record.original.display(), record.destination.display());
+                conflicts += 1;
+            },
+            Ok(Outcome::Missing)    => {
+                println!("Missing: {}", record.destination.display());
+                missing += 1;
+            },
+            Err(why)                => {
+                println!("Restore failed ({}): {}", why, record.destination.display());
+                failed += 1;
+            },
         }
-    } else {
-        println!("Removed: {}", file_path);
     }
+    println!("restored: {}, conflicts: {}, missing: {}, failed: {}", restored, conflicts, missing, failed);
+    if failed > 0 { 1 } else { 0 }
+}
+
+/// Whether the filesystem holding `path` has the free space asked by
+/// --min-free, counting the `unfreed` bytes of the action on it as free.
+fn watermark_restored(path: &Path, watermark: Watermark, unfreed: u64) -> Result<bool, Error> {
+    let mut stat = disk::stat_fs(path)?;
+    stat.avail += unfreed;
+    Ok(watermark.deficit(&stat) == 0)
+}
+
+/// Seconds from `time` to `now`, 0 for a time in the future.
+fn age_secs(now: SystemTime, time: SystemTime) -> u64 {
+    now.duration_since(time).map(|age| age.as_secs()).unwrap_or(0)
+}
+
+/// The record of what was done to the target at `path`, scanned as `f`, with
+/// its age as of `now`; `Ok` holds where it was moved to, if it was.
+fn action_record(action: &dyn Action, path: String, f: &Entry, now: SystemTime, result: Result<Option<&Path>, &Failure>) -> report::Record {
+    report::Record::Action {
+        path,
+        size:           f.size,
+        age_secs:       age_secs(now, f.time),
+        action:         action.name(),
+        result:         result.map_or_else(Failure::result, |_| "ok"),
+        destination:    result.ok().flatten().map(|dest| dest.display().to_string()),
+        error:          result.err().and_then(Failure::reason),
+        dry_run:        action.dry_run(),
+    }
+}
+
+/// Ask about `target` with -i, then act on it, returning where it was moved
+/// to, if it was.
+fn remove_target(action: &mut dyn Action, target: &Target, config: &Config) -> Result<Option<PathBuf>, Failure>{
+    let text = config.output() == Format::Text;
 
     if config.do_intr() {
-        println!("");
+        if text {
+            println!("    {:?}", target.entry.name);
+        } else {
+            eprintln!("    {}", target.path.display());
+        }
+        match interaction("Remove This file? [Y/n]: ", config.assume_yes(), text) {
+            Ok(_)   => {},
+            Err(_)  => {return Err(Failure::Canceled);}
+        }
+    }
+    let dest = action.apply(target)?;
+
+    if text {
+        println!("{}", action.describe(target, dest.as_deref()));
+        if config.do_intr() {
+            println!();
+        }
     }
 
-    Ok(())
+    Ok(dest)
 }
 
-fn interaction(msg: &str, assume_yes: bool) -> Result<(), ()> {
+/// Ask `msg` on the terminal. Without `text` stdout only holds records, so
+/// the question goes to stderr.
+fn interaction(msg: &str, assume_yes: bool, text: bool) -> Result<(), ()> {
     let mut _ret: Result<(), ()> = Ok(());
 
     if assume_yes {
         return _ret;
     }
 
-    print!("{}", msg);
-    io::stdout().flush().unwrap();
+    let ask = |msg: &str| {
+        if text {
+            print!("{}", msg);
+            io::stdout().flush().unwrap();
+        } else {
+            eprint!("{}", msg);
+        }
+    };
+    ask(msg);
 
     loop{
-        let s = get_string().unwrap().as_str().chars().nth(0).unwrap();
+        let s = get_string().unwrap().chars().next();
         match s {
-            'Y' => {
+            Some('Y') => {
                 _ret = Ok(());
                 break;
             }
-            'n' => {
+            // EOF on stdin is treated as "no".
+            Some('n') | None => {
                 _ret = Err(());
                 break;
             }
             _   => {
-                print!("Invalid value. Please input [Y/n].\n{}", msg);
-                io::stdout().flush().unwrap();
+                ask(&format!("Invalid value. Please input [Y/n].\n{}", msg));
             },
         }
     }
@@ -160,4 +678,46 @@ fn get_string() -> io::Result<String> {
     let mut buf = String::new();
     io::stdin().read_line(&mut buf)?;
     Ok(buf)
-}
\ No newline at end of file
+}
+
+
+#[cfg(test)]
+#[path = "../testutil.rs"]
+mod testutil;
+
+#[cfg(test)]
+mod test{
+    use super::*;
+    use crate::testutil::TempDir;
+    use std::fs::File;
+    use std::time::Duration;
+
+    #[test]
+    fn test_exit_status() {
+        let dir = TempDir::new("exit");
+        fs::create_dir_all(dir.join("data/sub")).unwrap();
+        File::create(dir.join("data/sub/a.log")).unwrap().set_modified(SystemTime::now() - Duration::from_secs(86400)).unwrap();
+        fs::write(dir.join("blocker"), "").unwrap();
+        let path = |rel: &str| dir.join(rel).display().to_string();
+        let status = |args: &[String]| {
+            let mut argv = vec![OsString::from("rm-old")];
+            argv.extend(args.iter().map(OsString::from));
+            run(argv)
+        };
+        let move_to = |dest: &str, journal: &str| status(&[path("data"), "-r".to_string(), "-d".to_string(), "1".to_string(), "-y".to_string(),
+                                            format!("--move-to={}", path(dest)), format!("--journal={}", path(journal))]);
+
+        // Moving under a file fails.
+        assert_eq!(1, move_to("blocker/moved", "failed.tsv"));
+        assert!(dir.join("data/sub/a.log").exists());
+        assert_eq!(0, move_to("moved", "moved.tsv"));
+
+        // Its dir is now a file, so the file can not go back.
+        fs::remove_dir(dir.join("data/sub")).unwrap();
+        fs::write(dir.join("data/sub"), "").unwrap();
+        assert_eq!(1, status(&["restore".to_string(), path("moved.tsv")]));
+        fs::remove_file(dir.join("data/sub")).unwrap();
+        assert_eq!(0, status(&["restore".to_string(), path("moved.tsv")]));
+        assert!(dir.join("data/sub/a.log").exists());
+    }
+}
@@ -0,0 +1,107 @@
+use crate::error::Error;
+use crate::size;
+
+use std::io;
+use std::path::Path;
+
+/// Capacity of the filesystem holding a path, as reported by statvfs.
+#[derive(Clone, Copy, Debug)]
+pub struct FsStat {
+    /// Identifies the filesystem, so that paths on the same one can be
+    /// grouped.
+    pub id:     u64,
+    pub total:  u64,
+    /// Bytes available to unprivileged users.
+    pub avail:  u64,
+}
+
+#[cfg(unix)]
+pub fn stat_fs(path: &Path) -> Result<FsStat, Error> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|why| Error::io(path, why.into()))?;
+    let mut buf: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut buf) } != 0 {
+        return Err(Error::io(path, io::Error::last_os_error()));
+    }
+
+    let frsize = if buf.f_frsize > 0 { buf.f_frsize as u64 } else { buf.f_bsize as u64 };
+    Ok(FsStat {
+        id:     buf.f_fsid as u64,
+        total:  buf.f_blocks as u64 * frsize,
+        avail:  buf.f_bavail as u64 * frsize,
+    })
+}
+
+#[cfg(not(unix))]
+pub fn stat_fs(path: &Path) -> Result<FsStat, Error> {
+    Err(Error::io(path, io::Error::new(io::ErrorKind::Unsupported, "can not statvfs on this platform")))
+}
+
+/// Free space to be kept on a filesystem, given as "10%" of its size or as
+/// a size like "50G".
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Watermark {
+    Percent(f64),
+    Bytes(u64),
+}
+
+impl Watermark {
+    pub fn parse(s: &str) -> Result<Watermark, Error> {
+        match s.strip_suffix('%') {
+            Some(num)   => {
+                match num.parse::<f64>() {
+                    Ok(percent) if (0.0..=100.0).contains(&percent) => Ok(Watermark::Percent(percent)),
+                    _ => Err(Error::InvalidArgument(format!("rm-old --min-free: Illegal value: {}", s))),
+                }
+            },
+            None        => Ok(Watermark::Bytes(size::parse_size(s)?)),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Watermark::Percent(percent) => format!("{}%", percent),
+            Watermark::Bytes(bytes)     => size::format_size(*bytes),
+        }
+    }
+
+    /// Bytes that must still be freed on `stat` to reach the watermark.
+    pub fn deficit(&self, stat: &FsStat) -> u64 {
+        let required = match self {
+            Watermark::Percent(percent) => (stat.total as f64 * percent / 100.0).ceil() as u64,
+            Watermark::Bytes(bytes)     => *bytes,
+        };
+        required.saturating_sub(stat.avail)
+    }
+}
+
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn test_watermark() {
+        let stat = FsStat { id: 0, total: 1000, avail: 50 };
+
+        assert_eq!(Watermark::Percent(10.0), Watermark::parse("10%").unwrap());
+        assert_eq!(Watermark::Bytes(2048), Watermark::parse("2K").unwrap());
+        assert!(Watermark::parse("101%").is_err());
+        assert!(Watermark::parse("x%").is_err());
+
+        assert_eq!(50, Watermark::Percent(10.0).deficit(&stat));
+        assert_eq!(0, Watermark::Percent(5.0).deficit(&stat));
+        assert_eq!(150, Watermark::Bytes(200).deficit(&stat));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_stat_fs() {
+        let stat = stat_fs(Path::new(".")).unwrap();
+        assert!(stat.total >= stat.avail);
+        assert!(stat_fs(Path::new("not/exists/path")).is_err());
+    }
+}
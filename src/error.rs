@@ -0,0 +1,82 @@
+//! The error type of the library.
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum Error {
+    /// A bad option, value or combination of them.
+    InvalidArgument(String),
+    /// Not a failure: -h asked for this help text.
+    Help(String),
+    /// A file or dir could not be read, stat'ed or opened.
+    Io {
+        path:   PathBuf,
+        source: io::Error,
+    },
+    /// The age cutoff falls before the epoch, as with a clock set far back.
+    ClockSkew(String),
+    /// A line of an ignore file is not a valid pattern.
+    IgnoreFile(String),
+    /// There is nothing to remove in the given dirs.
+    NoTargets,
+}
+
+impl Error {
+    pub fn io(path: &Path, source: io::Error) -> Error {
+        Error::Io {
+            path:   path.to_path_buf(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidArgument(err_msg)
+                | Error::Help(err_msg)
+                | Error::ClockSkew(err_msg)
+                | Error::IgnoreFile(err_msg)    => write!(f, "{}", err_msg),
+            Error::Io { path, source }          => write!(f, "rm-old: {}: {}", path.display(), source),
+            Error::NoTargets                    => write!(f, "Target files not exists!"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. }    => Some(source),
+            _                           => None,
+        }
+    }
+}
+
+/// The `io::Error` behind `err`, its path aside, or `err` as one.
+impl From<Error> for io::Error {
+    fn from(err: Error) -> io::Error {
+        match err {
+            Error::Io { source, .. }    => source,
+            err                         => io::Error::other(err.to_string()),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn test_error() {
+        let err = Error::io(Path::new("/data"), io::Error::from(io::ErrorKind::PermissionDenied));
+        assert!(err.to_string().starts_with("rm-old: /data: "));
+        assert!(matches!(&err, Error::Io { source, .. } if source.kind() == io::ErrorKind::PermissionDenied));
+        assert!(error::Error::source(&err).is_some());
+        assert!(error::Error::source(&Error::NoTargets).is_none());
+        assert_eq!(io::ErrorKind::PermissionDenied, io::Error::from(err).kind());
+    }
+}
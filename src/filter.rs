@@ -0,0 +1,163 @@
+//! Predicates deciding which entries may be removed, for library users to
+//! add rules of their own, e.g. to spare files still referenced somewhere.
+//! The age check of the command line is `Age`; filters given to
+//! `ConfigBuilder::filter` must pass as well.
+//!
+//! ```no_run
+//! use rm_old::arg::ConfigBuilder;
+//! use rm_old::filter::{self, Filter};
+//!
+//! let small = filter::from_fn(|_path, meta| meta.len() < 1 << 20);
+//! let shared = filter::from_fn(|path, _meta| path.starts_with("/var/tmp/cache/shared"));
+//! let config = ConfigBuilder::new()
+//!     .target("/var/tmp/cache")
+//!     .filter(small.and(shared.not()))
+//!     .build()
+//!     .unwrap();
+//! ```
+
+use crate::arg::TimeField;
+use crate::fs::get_file_time;
+use crate::time::TimeWindow;
+
+use std::fs::Metadata;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Decides for each scanned entry, by its path and lstat metadata, whether
+/// it may be removed. The --keep-* rules are applied apart from it, so an
+/// entry kept by them is never removed either way. Scans run on several
+/// threads, hence `Send + Sync`.
+pub trait Filter: Send + Sync {
+    fn accepts(&self, path: &Path, metadata: &Metadata) -> bool;
+
+    /// Entries both filters accept.
+    fn and<F: Filter>(self, other: F) -> And<Self, F>
+    where
+        Self: Sized,
+    {
+        And(self, other)
+    }
+
+    /// Entries either filter accepts.
+    fn or<F: Filter>(self, other: F) -> Or<Self, F>
+    where
+        Self: Sized,
+    {
+        Or(self, other)
+    }
+
+    /// Entries this filter rejects.
+    fn not(self) -> Not<Self>
+    where
+        Self: Sized,
+    {
+        Not(self)
+    }
+}
+
+pub struct And<A, B>(pub A, pub B);
+
+impl<A: Filter, B: Filter> Filter for And<A, B> {
+    fn accepts(&self, path: &Path, metadata: &Metadata) -> bool {
+        self.0.accepts(path, metadata) && self.1.accepts(path, metadata)
+    }
+}
+
+pub struct Or<A, B>(pub A, pub B);
+
+impl<A: Filter, B: Filter> Filter for Or<A, B> {
+    fn accepts(&self, path: &Path, metadata: &Metadata) -> bool {
+        self.0.accepts(path, metadata) || self.1.accepts(path, metadata)
+    }
+}
+
+pub struct Not<F>(pub F);
+
+impl<F: Filter> Filter for Not<F> {
+    fn accepts(&self, path: &Path, metadata: &Metadata) -> bool {
+        !self.0.accepts(path, metadata)
+    }
+}
+
+/// Entries every filter accepts; with none, all of them.
+#[derive(Clone, Default)]
+pub struct All(pub Vec<Arc<dyn Filter>>);
+
+impl Filter for All {
+    fn accepts(&self, path: &Path, metadata: &Metadata) -> bool {
+        self.0.iter().all(|filter| filter.accepts(path, metadata))
+    }
+}
+
+/// The age check: entries whose `field` timestamp is in `window`. An entry
+/// without that timestamp is rejected.
+#[derive(Clone, Copy, Debug)]
+pub struct Age {
+    pub window: TimeWindow,
+    pub field:  TimeField,
+}
+
+impl Filter for Age {
+    fn accepts(&self, path: &Path, metadata: &Metadata) -> bool {
+        get_file_time(metadata, self.field, path).is_ok_and(|time| self.window.contains(time))
+    }
+}
+
+/// A filter made of a closure.
+pub struct FnFilter<F>(F);
+
+pub fn from_fn<F>(f: F) -> FnFilter<F>
+where
+    F: Fn(&Path, &Metadata) -> bool + Send + Sync,
+{
+    FnFilter(f)
+}
+
+impl<F> Filter for FnFilter<F>
+where
+    F: Fn(&Path, &Metadata) -> bool + Send + Sync,
+{
+    fn accepts(&self, path: &Path, metadata: &Metadata) -> bool {
+        (self.0)(path, metadata)
+    }
+}
+
+impl<F: Filter + ?Sized> Filter for Box<F> {
+    fn accepts(&self, path: &Path, metadata: &Metadata) -> bool {
+        (**self).accepts(path, metadata)
+    }
+}
+
+impl<F: Filter + ?Sized> Filter for Arc<F> {
+    fn accepts(&self, path: &Path, metadata: &Metadata) -> bool {
+        (**self).accepts(path, metadata)
+    }
+}
+
+
+#[cfg(test)]
+mod test{
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_filter() {
+        let path = Path::new("Cargo.toml");
+        let meta = std::fs::symlink_metadata(path).unwrap();
+        let yes = from_fn(|_: &Path, _: &Metadata| true);
+        let no = from_fn(|_: &Path, _: &Metadata| false);
+
+        assert!(!yes.and(no).accepts(path, &meta));
+        let (yes, no) = (from_fn(|_: &Path, _: &Metadata| true), from_fn(|_: &Path, _: &Metadata| false));
+        assert!(yes.or(no).accepts(path, &meta));
+        assert!(from_fn(|_: &Path, _: &Metadata| false).not().accepts(path, &meta));
+        assert!(All::default().accepts(path, &meta));
+
+        let now = SystemTime::now() + Duration::from_secs(60);
+        let age = Age { window: TimeWindow { older: now, newer: None }, field: TimeField::Mtime };
+        assert!(age.accepts(path, &meta));
+        let boxed: Box<dyn Filter> = Box::new(age.not());
+        assert!(!boxed.accepts(path, &meta));
+    }
+}
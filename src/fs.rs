@@ -1,204 +1,716 @@
-use crate::arg::Config;
+use crate::arg::{self, Config, TimeField};
+use crate::disk::{self, FsStat, Watermark};
+use crate::error::Error;
+use crate::filter::Filter;
+use crate::glob::Pattern;
+use crate::ignore::{self, IgnoreStack, IGNORE_FILE};
+use crate::pathenc;
+use crate::retention::Kept;
 
+use std::collections::{HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
 use std::time::SystemTime;
-use std::fs::{self, File};
-use std::sync::mpsc;
+use std::fs::{self, Metadata};
+use std::io::{self, Read};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{self as std_path, Path, PathBuf};
+use std::sync::{Condvar, Mutex};
 use std::thread;
 
 
+/// A file (or directory with `--remove-dir`) in a `Dir`, with the timestamp
+/// chosen by `--time-field`. `size` is 0 for directories. `dev` and `ino`
+/// identify the file as it was scanned (0 where the platform has none).
+/// `name` need not be UTF-8.
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub name:   OsString,
+    pub time:   SystemTime,
+    pub size:   u64,
+    pub dev:    u64,
+    pub ino:    u64,
+}
+
+impl Entry {
+    /// The name for output, with bytes that are not UTF-8 replaced.
+    pub fn display(&self) -> std_path::Display<'_> {
+        Path::new(&self.name).display()
+    }
+}
+
+/// A dir or file the scan could not read and passed over.
+#[derive(Debug)]
+pub struct Skipped {
+    pub path:   PathBuf,
+    pub error:  Error,
+}
+
+impl Skipped {
+    /// Why it was passed over, without `path`.
+    pub fn reason(&self) -> String {
+        match &self.error {
+            Error::Io { path, source } if *path == self.path    => source.to_string(),
+            Error::Io { path, source }                          => format!("{}: {}", path.display(), source),
+            err                                                 => err.to_string(),
+        }
+    }
+}
 
 pub struct Dir{
-    pub parent_path:    String,
-    pub files_path:     Vec<String>,
+    pub parent_path:    PathBuf,
+    /// Path of the dir relative to its target, "" for the target itself.
+    pub rel_path:       PathBuf,
+    pub files:          Vec<Entry>,
+    pub kept:           Vec<Kept>,
+    /// Paths in the dir, or the dir itself, that could not be read.
+    pub skipped:        Vec<Skipped>,
+    /// Names of the scanned entries the filter rejected, until `select`.
+    rejected:           HashSet<OsString>,
+    /// Size of all regular files directly in the dir, selected or not.
+    pub total_size:     u64,
 }
 
 impl Dir {
-    pub fn new(path: &String) -> Dir {
+    pub fn new(path: &Path) -> Dir {
         Dir {
-            parent_path:    path.clone(),
-            files_path:     Vec::new(),
+            parent_path:    path.to_path_buf(),
+            rel_path:       PathBuf::new(),
+            files:          Vec::new(),
+            kept:           Vec::new(),
+            skipped:        Vec::new(),
+            rejected:       HashSet::new(),
+            total_size:     0,
         }
     }
 
-    pub fn get_target_files(config: &Config) -> Result<Vec<Dir>, String> {
+    /// The dirs holding the files selected by `config`. A dir or file that
+    /// can not be read is passed over and recorded in `skipped` of its dir,
+    /// or with --fail-fast fails the call.
+    pub fn get_target_files(config: &Config) -> Result<Vec<Dir>, Error> {
         let now_sys_time             = SystemTime::now();
+        let filter                   = config.selection(config.time_window(now_sys_time)?);
         let mut targets: Vec<Dir>    = Vec::new();
 
-        if !config.remove_dir(){
+        if let Some(list) = config.from_file() {
+            targets = get_listed_files(&read_list(list, config.null())?, config, &filter)?;
+        } else if !config.remove_dir(){
+            // --min-free is about a filesystem, so the targets on one are
+            // selected from together.
+            let mut on_fs: Vec<(u64, FsStat, Vec<Dir>)> = Vec::new();
             for path in config.get_target_path().iter() {
-                let mut t = match get_files_in_dir(path, config.clone(), now_sys_time) {
-                    Ok(files)       => files,
-                    Err(err_msg)    => return Err(err_msg),
-                };
-                targets.append(&mut t);
+                let mut t = walk_tree(path, config, &filter)?;
+                if let Some(budget) = config.max_total_size() {
+                    let usage: u64 = t.iter().map(|dir| dir.total_size).sum();
+                    select_oldest(&mut t, usage.saturating_sub(budget));
+                }
+                if config.min_free().is_none() {
+                    targets.append(&mut t);
+                    continue;
+                }
+                let (dev, _) = get_file_id(&fs::metadata(path).map_err(|why| Error::io(path, why))?);
+                match on_fs.iter_mut().find(|(fs_dev, _, _)| *fs_dev == dev) {
+                    Some((_, _, dirs))  => dirs.append(&mut t),
+                    None                => on_fs.push((dev, disk::stat_fs(path)?, t)),
+                }
+            }
+            if let Some(watermark) = config.min_free() {
+                targets = select_for_free(on_fs, watermark);
             }
         } else {
             for path in config.get_target_path().iter() {
-                let t = match get_dirs_in_dir(path, &config, now_sys_time) {
-                    Ok(dirs)       => dirs,
-                    Err(err_msg)    => return Err(err_msg),
-                };
+                let t = get_dirs_in_dir(path, config, &filter)?;
                 targets.push(t);
             }
         }
-        println!("{:?}", SystemTime::now().duration_since(now_sys_time));
         Ok(targets)
     }
 
-    pub fn print(&self) -> () {
-        println!("{}/:",self.parent_path);
-        for file in self.files_path.iter().rev() {
-            println!("    {}", file);
+    pub fn print(&self) {
+        println!("{}/:",self.parent_path.display());
+        for file in self.files.iter() {
+            println!("    {}", file.display());
         }
-        println!("");
+        println!();
+    }
+
+    /// Show the entries kept by the retention policy or protected by an
+    /// ignore file, and why.
+    pub fn print_kept(&self) {
+        if self.kept.is_empty() {
+            return;
+        }
+        println!("{}/: kept", self.parent_path.display());
+        for kept in self.kept.iter() {
+            println!("    {} ({})", kept.entry.display(), kept.reason);
+        }
+        println!();
     }
 
     pub fn get_amount_files(&self) -> u64 {
-        self.files_path.len() as u64
+        self.files.len() as u64
+    }
+
+    pub fn get_amount_size(&self) -> u64 {
+        self.files.iter().map(|entry| entry.size).sum()
     }
 
-    pub fn get_parent_path(&self) -> &String {
+    pub fn get_parent_path(&self) -> &Path {
         &self.parent_path
     }
 
-    pub fn get_files(&self) -> &Vec<String> {
-        &self.files_path
+    /// Path of the target this dir was found in.
+    pub fn get_target_path(&self) -> &Path {
+        self.parent_path.ancestors().nth(self.rel_path.components().count()).unwrap_or(&self.parent_path)
     }
+
+    /// Path of the entry `name` of this dir.
+    pub fn get_path(&self, name: &OsStr) -> PathBuf {
+        self.parent_path.join(name)
+    }
+
+    /// Path of the entry `name` of this dir relative to its target.
+    pub fn get_rel_path(&self, name: &OsStr) -> PathBuf {
+        self.rel_path.join(name)
+    }
+
+    pub fn get_files(&self) -> &Vec<Entry> {
+        &self.files
+    }
+
+    /// Narrow the scanned entries down to the removal targets: entries kept
+    /// by the retention policy survive, the rest are removed if the filter
+    /// accepted them. The result is ordered oldest first.
+    fn select(&mut self, config: &Config) {
+        let entries = std::mem::take(&mut self.files);
+        let rejected = std::mem::take(&mut self.rejected);
+        let (mut eligible, kept) = config.policy().apply(entries);
+        eligible.retain(|entry| !rejected.contains(&entry.name));
+        self.files = eligible;
+        self.kept = kept;
+    }
+}
+
+/// Return the timestamp selected by `field`, failing when the platform or
+/// filesystem can not provide it.
+pub fn get_file_time(meta: &Metadata, field: TimeField, path: &Path) -> Result<SystemTime, Error> {
+    let time = match field {
+        TimeField::Atime => meta.accessed(),
+        TimeField::Mtime => meta.modified(),
+        TimeField::Btime => meta.created(),
+        TimeField::Ctime => return get_ctime(meta, path),
+    };
+    time.map_err(|why| Error::io(path, io::Error::new(why.kind(), format!("can not get {}: {}", field.name(), why))))
+}
+
+/// The device and inode numbers of a file.
+#[cfg(unix)]
+pub fn get_file_id(meta: &Metadata) -> (u64, u64) {
+    use std::os::unix::fs::MetadataExt;
+    (meta.dev(), meta.ino())
+}
+
+#[cfg(not(unix))]
+pub fn get_file_id(_meta: &Metadata) -> (u64, u64) {
+    (0, 0)
+}
+
+#[cfg(unix)]
+fn get_ctime(meta: &Metadata, _path: &Path) -> Result<SystemTime, Error> {
+    use std::os::unix::fs::MetadataExt;
+    use std::time::Duration;
+
+    let since_epoch = Duration::new(meta.ctime().max(0) as u64, meta.ctime_nsec().max(0) as u32);
+    Ok(SystemTime::UNIX_EPOCH + since_epoch)
+}
+
+#[cfg(not(unix))]
+fn get_ctime(_meta: &Metadata, path: &Path) -> Result<SystemTime, Error> {
+    Err(Error::io(path, io::Error::new(io::ErrorKind::Unsupported, "can not get ctime on this platform")))
+}
+
+/// Pass over `path`, which could not be read, recording why in `skipped`;
+/// with --fail-fast fail with `error` instead.
+fn skip(config: &Config, skipped: &mut Vec<Skipped>, path: &Path, error: Error) -> Result<(), Error> {
+    if config.fail_fast() {
+        return Err(error);
+    }
+    skipped.push(Skipped { path: path.to_path_buf(), error });
+    Ok(())
+}
+
+/// Whether any of `patterns` matches the entry, by its path relative to the
+/// target or by its base name.
+fn glob_match(patterns: &[Pattern], rel: &str, name: &str) -> bool {
+    patterns.iter().any(|pattern| pattern.matches(rel) || pattern.matches(name))
+}
+
+/// Whether --include/--exclude and --regex/--exclude-regex let the entry
+/// through.
+pub(crate) fn is_included(config: &Config, rel: &str, name: &str) -> bool {
+    (config.include().is_empty() || glob_match(config.include(), rel, name))
+        && (config.regex().is_empty() || config.regex().iter().any(|regex| regex.is_match(rel)))
+        && !is_excluded(config, rel, name)
+}
+
+/// Whether --exclude/--exclude-regex reject the entry. Rejected dirs are
+/// not looked into.
+pub(crate) fn is_excluded(config: &Config, rel: &str, name: &str) -> bool {
+    glob_match(config.exclude(), rel, name)
+        || config.exclude_regex().iter().any(|regex| regex.is_match(rel))
+}
+
+/// A dir waiting to be scanned. `order` is its position in the tree, as the
+/// indexes of the dirs leading to it, so that the results can be put back in
+/// the order of a walk that lists each dir before the dirs in it.
+struct Task {
+    path:   PathBuf,
+    /// Path of `path` relative to the target, "" for the target itself.
+    rel:    PathBuf,
+    /// The rules of the ignore files above `path`, shared with its siblings.
+    ignore: IgnoreStack,
+    order:  Vec<usize>,
 }
 
-fn get_files_in_dir(path: &String, config: Config, now: SystemTime) -> Result<Vec<Dir>, String> {
-    let mut target: Vec<Dir> = Vec::new();
+/// The dirs still to be scanned and what the scanned ones gave.
+struct Walk {
+    /// Taken from the back, so that the walk goes depth first and the
+    /// queue stays small.
+    tasks:      Vec<Task>,
+    /// Workers scanning a dir, which may add more tasks.
+    busy:       usize,
+    found:      Vec<(Vec<usize>, Dir)>,
+    /// With --fail-fast, the first error, which ends the walk.
+    error:      Option<Error>,
+}
+
+/// Scan the tree of the target `path` with `config.jobs()` threads, each
+/// taking the next dir from a shared queue. The dirs come out as from a walk
+/// listing each dir before the dirs in it. A dir that can not be scanned is
+/// left out and recorded as skipped, unless --fail-fast.
+fn walk_tree(path: &Path, config: &Config, filter: &dyn Filter) -> Result<Vec<Dir>, Error> {
+    let walk = Mutex::new(Walk {
+        tasks:      vec![Task { path: path.to_path_buf(), rel: PathBuf::new(), ignore: IgnoreStack::new(), order: Vec::new() }],
+        busy:       0,
+        found:      Vec::new(),
+        error:      None,
+    });
+    let wake = Condvar::new();
+
+    thread::scope(|scope| {
+        for _ in 0..config.jobs() {
+            scope.spawn(|| walk_worker(&walk, &wake, config, filter));
+        }
+    });
+
+    let walk = walk.into_inner().unwrap();
+    if let Some(err) = walk.error {
+        return Err(err);
+    }
+    let mut found = walk.found;
+    found.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(found.into_iter().map(|(_, dir)| dir).collect())
+}
+
+fn walk_worker(walk: &Mutex<Walk>, wake: &Condvar, config: &Config, filter: &dyn Filter) {
+    loop {
+        let task = {
+            let mut state = walk.lock().unwrap();
+            loop {
+                if let Some(task) = state.tasks.pop() {
+                    state.busy += 1;
+                    break task;
+                }
+                // Nothing queued and nobody left to queue more: done.
+                if state.busy == 0 {
+                    return;
+                }
+                state = wake.wait(state).unwrap();
+            }
+        };
+
+        // A panic must still count the task as done, or the others would
+        // wait for it forever.
+        let result = panic::catch_unwind(AssertUnwindSafe(|| get_files_in_dir(&task, config, filter)))
+            .unwrap_or_else(|_| Err(Error::io(&task.path, io::Error::other("the scan panicked"))));
+        let mut state = walk.lock().unwrap();
+        state.busy -= 1;
+        match result {
+            Ok((dir, mut sub_dirs)) => {
+                if let Some(dir) = dir {
+                    state.found.push((task.order, dir));
+                }
+                // Reversed, so that they are taken in the order they were found.
+                sub_dirs.reverse();
+                if state.error.is_none() {
+                    state.tasks.append(&mut sub_dirs);
+                }
+            },
+            Err(err) if config.fail_fast()  => {
+                if state.error.is_none() {
+                    state.error = Some(err);
+                }
+                state.tasks.clear();
+            },
+            Err(err)                        => {
+                let mut dir = Dir::new(&task.path);
+                dir.rel_path = task.rel.clone();
+                dir.skipped.push(Skipped { path: task.path.clone(), error: err });
+                state.found.push((task.order, dir));
+            },
+        }
+        wake.notify_all();
+    }
+}
+
+/// Scan the dir of `task`: the `Dir` of its selected and kept entries, if it
+/// is to be listed, and the sub dirs to scan next with -r.
+fn get_files_in_dir(task: &Task, config: &Config, filter: &dyn Filter) -> Result<(Option<Dir>, Vec<Task>), Error> {
+    let path = task.path.as_path();
+    let rel = task.rel.as_path();
     let mut search_dir: Dir = Dir::new(path);
-    let mut thread_pool: Vec<ThreadNode> = Vec::new();
+    search_dir.rel_path = rel.to_path_buf();
+    let mut protected: Vec<Kept> = Vec::new();
+    let mut sub_dirs: Vec<Task> = Vec::new();
+    let ignore = task.ignore.push_dir(path, &rel.to_string_lossy())?;
 
-    let files = match fs::read_dir(path) {
-        Err(why)    => {
-            return Err(format!("Can not open dir: {:?}", why.kind()));
-        },
-        Ok(paths)   => paths,
-    };
+    let files = fs::read_dir(path).map_err(|why| Error::io(path, why))?;
 
     for f in files {
-        let file_path           = f.unwrap().path();
-        let file_meta           = File::open(&file_path).unwrap().metadata().unwrap();
-
-        let duration_time_by_ac = match now.duration_since(file_meta.accessed().unwrap()){
-            Ok(duration)    => duration.as_secs(),
-            Err(e)          => {
-                return Err(format!("Clock may have gone backwards: {:?}",e.duration()));
+        let file_path           = match f {
+            Ok(f)       => f.path(),
+            Err(why)    => {
+                skip(config, &mut search_dir.skipped, path, Error::io(path, why))?;
+                continue;
+            },
+        };
+        // lstat: a symlink is an entry of its own and never leads out of the
+        // tree. A file removed since the dir was listed fails here.
+        let file_meta           = match fs::symlink_metadata(&file_path) {
+            Ok(meta)    => meta,
+            Err(why)    => {
+                skip(config, &mut search_dir.skipped, &file_path, Error::io(&file_path, why))?;
+                continue;
             },
         };
 
-        if file_meta.is_file() && (config.get_duration_days() * 86400 < duration_time_by_ac) {
+        let file_time           = match get_file_time(&file_meta, config.time_field(), &file_path) {
+            Ok(time)    => time,
+            Err(err)    => {
+                skip(config, &mut search_dir.skipped, &file_path, err)?;
+                continue;
+            },
+        };
+        let file_name           = file_path.as_path().file_name().unwrap().to_os_string();
+        let file_rel            = rel.join(&file_name);
+        // The filters and ignore rules match text, so other names are
+        // matched with their bytes replaced.
+        let rel_text            = file_rel.to_string_lossy().into_owned();
+        let name_text           = file_name.to_string_lossy().into_owned();
+        let (dev, ino)          = get_file_id(&file_meta);
 
-            search_dir.files_path.push(
-                file_path.as_path().file_name().unwrap().to_str().unwrap().to_string());
+        let entry               = Entry {
+            name:   file_name,
+            time:   file_time,
+            size:   if file_meta.is_file() { file_meta.len() } else { 0 },
+            dev,
+            ino,
+        };
 
-        } else if file_meta.is_dir() && config.recursion() {
-            let (child_sender, child_reciever) = mpsc::channel::<Result<Vec<Dir>, String>>();
-            let tmp_config = config.clone();
+        // Symlinks are removed like files, the link and not its target.
+        if file_meta.is_file() || file_meta.file_type().is_symlink() {
+            search_dir.total_size += entry.size;
+            if entry.name == IGNORE_FILE || !is_included(config, &rel_text, &name_text) {
+                continue;
+            }
+            if let Some(source) = ignore.protected_by(&rel_text, false) {
+                protected.push(Kept { reason: format!("protected by {}", source), entry });
+                continue;
+            }
+            if !filter.accepts(&file_path, &file_meta) {
+                search_dir.rejected.insert(entry.name.clone());
+            }
+            search_dir.files.push(entry);
 
-            let handle = thread::spawn(move || {
-                let res = get_files_in_dir(
-                    &(file_path.as_path().to_str().unwrap().to_string()), tmp_config, now);
-                child_sender.send(res).unwrap();
+        } else if file_meta.is_dir() && config.recursion() && !is_excluded(config, &rel_text, &name_text) {
+            if let Some(source) = ignore.protected_by(&rel_text, true) {
+                protected.push(Kept { reason: format!("protected by {}", source), entry });
+                continue;
+            }
+            let mut order = task.order.clone();
+            order.push(sub_dirs.len());
+            sub_dirs.push(Task {
+                path:   file_path,
+                rel:    file_rel,
+                ignore: ignore.clone(),
+                order,
             });
-            thread_pool.push(ThreadNode::new(handle, child_reciever));
         }
     }
 
-    if !search_dir.files_path.is_empty() {
-        target.push(search_dir);
+    search_dir.select(config);
+    search_dir.kept.append(&mut protected);
+    // With a size quota every dir counts towards the usage of the target.
+    if !search_dir.files.is_empty() || !search_dir.kept.is_empty() || !search_dir.skipped.is_empty()
+        || config.max_total_size().is_some() {
+        Ok((Some(search_dir), sub_dirs))
+    } else {
+        Ok((None, sub_dirs))
     }
+}
 
-    if !thread_pool.is_empty() {
-        for node in thread_pool {
-            match node.listen(){
-                Ok(mut res) => {
-                    target.append(&mut res);
-                    for d in res {
-                        d.print();
-                    }
-                },
-                Err(err_msg) => println!("{}", err_msg),
-            }
-            node.handle.join().unwrap();
+/// Read the paths listed in `list` ("-" for stdin), one per line or, with
+/// `null`, each ended by a NUL. Empty entries are skipped.
+fn read_list(list: &str, null: bool) -> Result<Vec<PathBuf>, Error> {
+    let mut bytes = Vec::new();
+    let result = if list == "-" {
+        io::stdin().read_to_end(&mut bytes).map(|_| ())
+    } else {
+        fs::File::open(list).and_then(|mut file| file.read_to_end(&mut bytes)).map(|_| ())
+    };
+    result.map_err(|why| Error::io(Path::new(list), why))?;
+
+    let separator = if null { b'\0' } else { b'\n' };
+    bytes.split(|b| *b == separator)
+        .filter(|path| !path.is_empty())
+        .map(|path| {
+            pathenc::path_from_bytes(path.to_vec())
+                .ok_or_else(|| Error::InvalidArgument(format!("rm-old --from-file: not a valid path: {}", String::from_utf8_lossy(path))))
+        })
+        .collect()
+}
+
+/// The files of `paths`, grouped by their dir in the order they are first
+/// listed, each dir taken as its own target. Dirs in the list are passed
+/// over and the filters apply to the base names.
+fn get_listed_files(paths: &[PathBuf], config: &Config, filter: &dyn Filter) -> Result<Vec<Dir>, Error> {
+    let mut targets: Vec<Dir> = Vec::new();
+    let mut index: HashMap<PathBuf, (usize, Option<(IgnoreStack, String)>)> = HashMap::new();
+    let mut stacks: HashMap<PathBuf, IgnoreStack> = HashMap::new();
+    let mut protected: Vec<Vec<Kept>> = Vec::new();
+    // A path listed twice is acted on once; hard links are separate names.
+    let mut seen: HashSet<PathBuf> = HashSet::new();
+
+    for listed in paths.iter() {
+        let file_path           = listed.as_path();
+        let parent = arg::dir_path(file_path.parent().unwrap_or(Path::new("")).as_os_str());
+        let (i, ignore) = match index.get(&parent) {
+            Some(found) => found,
+            None        => {
+                let mut dir = Dir::new(&parent);
+                let ignore = match listed_ignore(&parent, &mut stacks) {
+                    Ok(ignore)  => Some(ignore),
+                    Err(err)    => {
+                        skip(config, &mut dir.skipped, &parent, err)?;
+                        None
+                    },
+                };
+                targets.push(dir);
+                protected.push(Vec::new());
+                index.entry(parent.clone()).or_insert((targets.len() - 1, ignore))
+            },
+        };
+        // Without the rules of its ignore files no file of the dir is known
+        // to be safe to remove.
+        let (ignore, parent_rel) = match ignore {
+            Some((ignore, rel)) => (ignore, rel),
+            None                => continue,
+        };
+        if file_path.file_name().is_some_and(|name| !seen.insert(parent.join(name))) {
+            continue;
+        }
+
+        let file_meta           = match fs::symlink_metadata(file_path) {
+            Ok(meta)    => meta,
+            Err(why)    => {
+                skip(config, &mut targets[*i].skipped, file_path, Error::io(file_path, why))?;
+                continue;
+            },
+        };
+        if file_meta.is_dir() {
+            continue;
+        }
+        let file_name           = match file_path.file_name() {
+            Some(name)  => name.to_os_string(),
+            None        => return Err(Error::InvalidArgument(format!("rm-old: illegal path: {}", listed.display()))),
+        };
+        let (dev, ino)          = get_file_id(&file_meta);
+        let file_time           = match get_file_time(&file_meta, config.time_field(), file_path) {
+            Ok(time)    => time,
+            Err(err)    => {
+                skip(config, &mut targets[*i].skipped, file_path, err)?;
+                continue;
+            },
+        };
+
+        let entry               = Entry {
+            name:   file_name,
+            time:   file_time,
+            size:   if file_meta.is_file() { file_meta.len() } else { 0 },
+            dev,
+            ino,
+        };
+        targets[*i].total_size += entry.size;
+        let name_text = entry.name.to_string_lossy().into_owned();
+        if entry.name == IGNORE_FILE || !is_included(config, &name_text, &name_text) {
+            continue;
+        }
+        let rel = if parent_rel.is_empty() { name_text.clone() } else { format!("{}/{}", parent_rel, name_text) };
+        match ignore.protected_by(&rel, false) {
+            Some(source)    => protected[*i].push(Kept { reason: format!("protected by {}", source), entry }),
+            None            => {
+                if !filter.accepts(file_path, &file_meta) {
+                    targets[*i].rejected.insert(entry.name.clone());
+                }
+                targets[*i].files.push(entry);
+            },
         }
     }
 
-    Ok(target)
+    for (dir, mut protected) in targets.iter_mut().zip(protected) {
+        dir.select(config);
+        dir.kept.append(&mut protected);
+    }
+    targets.retain(|dir| !dir.files.is_empty() || !dir.kept.is_empty() || !dir.skipped.is_empty());
+    // Callers walk the targets from the back, as they do the scanned dirs.
+    targets.reverse();
+    Ok(targets)
 }
 
-fn get_dirs_in_dir(path: &String, config: &Config, now: SystemTime) -> Result<Dir, String>{
-    let files = match fs::read_dir(path) {
-        Err(why)    => {
-            return Err(format!("Can not open dir: {:?}", why.kind()));
+fn get_dirs_in_dir(path: &Path, config: &Config, filter: &dyn Filter) -> Result<Dir, Error>{
+    let mut target_dir = Dir::new(path);
+    let mut protected: Vec<Kept> = Vec::new();
+    let scanned = fs::read_dir(path).map_err(|why| Error::io(path, why))
+        .and_then(|files| Ok((files, IgnoreStack::new().push_dir(path, "")?)));
+    let (files, ignore) = match scanned {
+        Ok(scanned) => scanned,
+        Err(err)    => {
+            skip(config, &mut target_dir.skipped, path, err)?;
+            return Ok(target_dir);
         },
-        Ok(paths)   => paths,
     };
 
-    let mut target_dir = Dir::new(path);
-
     for f in files {
-        let file_path           = f.unwrap().path();
-        let file_meta           = File::open(&file_path).unwrap().metadata().unwrap();
-        let duration_time_by_ac = match now.duration_since(file_meta.accessed().unwrap()){
-            Ok(duration)    => duration.as_secs(),
-            Err(e)          => {
-                return Err(format!("Clock may have gone backwards: {:?}",e.duration()));
+        let file_path           = match f {
+            Ok(f)       => f.path(),
+            Err(why)    => {
+                skip(config, &mut target_dir.skipped, path, Error::io(path, why))?;
+                continue;
+            },
+        };
+        // lstat: a symlink is an entry of its own and never leads out of the tree.
+        let file_meta           = match fs::symlink_metadata(&file_path) {
+            Ok(meta)    => meta,
+            Err(why)    => {
+                skip(config, &mut target_dir.skipped, &file_path, Error::io(&file_path, why))?;
+                continue;
             },
         };
+        let file_time           = match get_file_time(&file_meta, config.time_field(), &file_path) {
+            Ok(time)    => time,
+            Err(err)    => {
+                skip(config, &mut target_dir.skipped, &file_path, err)?;
+                continue;
+            },
+        };
+        let file_name           = file_path.as_path().file_name().unwrap().to_os_string();
+        let name_text           = file_name.to_string_lossy().into_owned();
 
-        if file_meta.is_dir() && (config.get_duration_days() * 86400 < duration_time_by_ac) {
-            target_dir.files_path.push(
-                file_path.as_path().file_name().unwrap().to_str().unwrap().to_string());
+        if file_meta.is_dir() && is_included(config, &name_text, &name_text) {
+            let (dev, ino) = get_file_id(&file_meta);
+            let entry = Entry {
+                name:   file_name,
+                time:   file_time,
+                size:   0,
+                dev,
+                ino,
+            };
+            // The whole dir goes, so an ignore file anywhere inside protects it.
+            if let Some(source) = ignore.protected_by(&name_text, true) {
+                protected.push(Kept { reason: format!("protected by {}", source), entry });
+            } else if ignore::contains_ignore_file(&file_path) {
+                protected.push(Kept { reason: format!("protected by an {} inside", IGNORE_FILE), entry });
+            } else {
+                if !filter.accepts(&file_path, &file_meta) {
+                    target_dir.rejected.insert(entry.name.clone());
+                }
+                target_dir.files.push(entry);
+            }
         }
     }
-    if !target_dir.files_path.is_empty() {
-        target_dir.parent_path = path.clone();
-        return Ok(target_dir);
+    target_dir.select(config);
+    target_dir.kept.append(&mut protected);
+    if !target_dir.files.is_empty() || !target_dir.kept.is_empty() || !target_dir.skipped.is_empty() {
+        target_dir.parent_path = path.to_path_buf();
+        Ok(target_dir)
     } else {
-        return Err(format!("Target directories not exists!"));
+        Err(Error::NoTargets)
     }
 }
 
-struct ThreadNode {
-    handle: thread::JoinHandle<()>,
-    listener: mpsc::Receiver<Result<Vec<Dir>, String>>,
+/// The ignore rules for the listed files in `dir`: those of every dir from
+/// the filesystem root down to it, with the path of `dir` relative to the
+/// root. `stacks` holds the stacks already read.
+fn listed_ignore(dir: &Path, stacks: &mut HashMap<PathBuf, IgnoreStack>) -> Result<(IgnoreStack, String), Error> {
+    let dir: PathBuf = std::path::absolute(dir).map_err(|why| Error::io(dir, why))?.components().collect();
+    let ancestors: Vec<&Path> = dir.ancestors().collect();
+    let root = ancestors[ancestors.len() - 1];
+    let mut stack = IgnoreStack::new();
+    for ancestor in ancestors.iter().rev() {
+        stack = match stacks.get(*ancestor) {
+            Some(found) => found.clone(),
+            None        => {
+                let rel = ancestor.strip_prefix(root).unwrap_or(ancestor).to_string_lossy().into_owned();
+                let pushed = stack.push_dir(ancestor, &rel)?;
+                stacks.insert(ancestor.to_path_buf(), pushed.clone());
+                pushed
+            },
+        };
+    }
+    let rel = dir.strip_prefix(root).unwrap_or(&dir).to_string_lossy().into_owned();
+    Ok((stack, rel))
 }
 
-impl ThreadNode {
-    fn new(handle: thread::JoinHandle<()>, listener: mpsc::Receiver<Result<Vec<Dir>, String>>) -> ThreadNode {
-        ThreadNode {
-            handle,
-            listener,
-        }
+/// Select on each filesystem the oldest candidates of all its targets,
+/// until enough is freed to restore `watermark`. `on_fs` holds the device,
+/// the stat and the dirs of the targets of each filesystem.
+fn select_for_free(on_fs: Vec<(u64, FsStat, Vec<Dir>)>, watermark: Watermark) -> Vec<Dir> {
+    let mut targets = Vec::new();
+    for (_, stat, mut dirs) in on_fs {
+        select_oldest(&mut dirs, watermark.deficit(&stat));
+        targets.append(&mut dirs);
     }
+    targets
+}
 
-    fn listen(&self) -> Result<Vec<Dir>, String> {
-        match self.listener.recv() {
-            Ok(res)       => {
-                match res {
-                    Ok(dir)    => {
-                        return Ok(dir);
-                    },
-                    Err(err_msg)    => return Err(err_msg),
-                }
-            },
-            Err(_)        => {
-                return Err("Can not recieve Message!".to_string());
-            }
-        };
+/// Narrow the candidates of `dirs` down to the oldest ones whose sizes
+/// add up to at least `need` bytes.
+fn select_oldest(dirs: &mut Vec<Dir>, need: u64) {
+    let mut freed: u64 = 0;
+    let mut candidates: Vec<(usize, Entry)> = Vec::new();
+    for (i, dir) in dirs.iter_mut().enumerate() {
+        candidates.extend(std::mem::take(&mut dir.files).into_iter().map(|entry| (i, entry)));
     }
+    candidates.sort_by_key(|(_, entry)| entry.time);
+
+    for (i, entry) in candidates {
+        if freed >= need {
+            break;
+        }
+        freed += entry.size;
+        dirs[i].files.push(entry);
+    }
+    dirs.retain(|dir| !dir.files.is_empty() || !dir.kept.is_empty() || !dir.skipped.is_empty());
 }
 
 #[cfg(test)]
 mod test{
     use super::*;
+    use crate::testutil::TempDir;
+    use std::fs::File;
 
     #[test]
     fn test_get_files() {
-        let config = Config::parse_config(&vec!["rm-old".to_string(), "test_dir".to_string(), "-d".to_string(), "0".to_string()]).unwrap();
+        let config = Config::parse_config(&["rm-old".to_string(), "test_dir".to_string(), "-d".to_string(), "0".to_string()]).unwrap();
 
         let mut test_dir = match Dir::get_target_files(&config) {
             Ok(dir)     => dir,
@@ -208,7 +720,7 @@ mod test{
 
         test_dir.clear();
 
-        let config = Config::parse_config(&vec!["rm-old".to_string(), "test_dir".to_string(), "-d".to_string(), "0".to_string(), "-r".to_string()]).unwrap();
+        let config = Config::parse_config(&["rm-old".to_string(), "test_dir".to_string(), "-d".to_string(), "0".to_string(), "-r".to_string()]).unwrap();
 
         test_dir = match Dir::get_target_files(&config) {
             Ok(dir)     => dir,
@@ -219,7 +731,7 @@ mod test{
 
         test_dir.clear();
 
-        let config = Config::parse_config(&vec!["rm-old".to_string(), "test_dir".to_string(), "-d".to_string(), "0".to_string(), "--remove-dir".to_string()]).unwrap();
+        let config = Config::parse_config(&["rm-old".to_string(), "test_dir".to_string(), "-d".to_string(), "0".to_string(), "--remove-dir".to_string()]).unwrap();
 
         test_dir = match Dir::get_target_files(&config) {
             Ok(dir)     => dir,
@@ -231,4 +743,325 @@ mod test{
 
         assert_eq!(1, test_dir.len());
     }
+
+    /// Create a fresh dir under the system temp dir holding `files`, each
+    /// given as (name, age in hours).
+    fn make_test_dir(name: &str, files: &[(&str, u64)]) -> TempDir {
+        let dir = TempDir::new(name);
+        let now = SystemTime::now();
+        for (file, hours) in files.iter() {
+            if let Some(parent) = Path::new(file).parent() {
+                fs::create_dir_all(dir.join(parent)).unwrap();
+            }
+            let f = File::create(dir.join(file)).unwrap();
+            f.set_len(1000).unwrap();
+            f.set_modified(now - std::time::Duration::from_secs(hours * 3600)).unwrap();
+        }
+        dir
+    }
+
+    /// The arguments of a run on `dir` with `args`.
+    fn argv(dir: &Path, args: &[&str]) -> Vec<OsString> {
+        let mut argv = vec![OsString::from("rm-old"), dir.as_os_str().to_os_string()];
+        argv.extend(args.iter().map(OsString::from));
+        argv
+    }
+
+    /// Sorted names of the files a run on `dir` with `args` selects.
+    fn selected_names(dir: &Path, args: &[&str]) -> Vec<String> {
+        config_names(&Config::parse_config(&argv(dir, args)).unwrap())
+    }
+
+    /// Sorted names of the files `config` selects.
+    fn config_names(config: &Config) -> Vec<String> {
+        let dirs = match Dir::get_target_files(config) {
+            Ok(dirs)                => dirs,
+            Err(Error::NoTargets)   => vec![],
+            Err(err)                => panic!("{}", err),
+        };
+        dir_names(&dirs)
+    }
+
+    /// Sorted names of the files selected in `dirs`.
+    fn dir_names(dirs: &[Dir]) -> Vec<String> {
+        let mut names: Vec<String> = dirs.iter().flat_map(|d| d.files.iter().map(|e| e.display().to_string())).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_keep_last() {
+        let dir = make_test_dir("keep-last", &[("a", 25), ("b", 49), ("c", 73), ("d", 97), ("e", 121)]);
+        let names = |args: &[&str]| selected_names(&dir, args);
+
+        assert_eq!(vec!["c", "d", "e"], names(&["--keep-last", "2"]));
+        assert_eq!(vec!["d", "e"], names(&["--keep-last", "2", "-d", "4"]));
+        assert_eq!(vec!["e"], names(&["--keep-last", "4", "-d", "1"]));
+        assert!(names(&["--keep-last", "5"]).is_empty());
+    }
+
+    #[test]
+    fn test_keep_buckets() {
+        let dir = make_test_dir("keep-buckets", &[("a", 1), ("b", 2), ("c", 49), ("d", 50), ("e", 24 * 400)]);
+        let config = Config::parse_config(&argv(&dir, &["--keep-daily=1", "--keep-yearly=5"])).unwrap();
+        let dirs = Dir::get_target_files(&config).unwrap();
+
+        assert_eq!(1, dirs.len());
+        assert_eq!(3, dirs[0].files.len());
+        assert!(dirs[0].kept.iter().any(|k| k.entry.name == "e" && k.reason.starts_with("yearly ")));
+    }
+
+    #[test]
+    fn test_max_total_size() {
+        let dir = make_test_dir("max-total-size", &[("a", 1), ("b", 2), ("sub/c", 3), ("sub/d", 4), ("e", 5)]);
+        let names = |args: &[&str]| selected_names(&dir, args);
+
+        // 3000 bytes at top level, 5000 bytes with -r.
+        assert_eq!(vec!["e"], names(&["--max-total-size", "2000"]));
+        assert_eq!(vec!["c", "d", "e"], names(&["--max-total-size", "2000", "-r"]));
+        assert!(names(&["--max-total-size", "5000", "-r"]).is_empty());
+        assert_eq!(vec!["b", "d", "e"], names(&["--max-total-size", "2500", "-r", "--keep-last", "1"]));
+        assert_eq!(vec!["d", "e"], names(&["--max-total-size", "0", "-r", "-d", "210m"]));
+    }
+
+    #[test]
+    fn test_select_for_free() {
+        let one = make_test_dir("free-one", &[("a", 1), ("b", 4)]);
+        let two = make_test_dir("free-two", &[("c", 2), ("d", 3)]);
+        let target = |dir: &Path| Dir::get_target_files(&Config::parse_config(&argv(dir, &["-d", "0"])).unwrap()).unwrap();
+
+        // 1500 bytes short on the filesystem of both targets: its two oldest
+        // files go, not the two oldest of each target.
+        let stat = FsStat { id: 1, total: 10000, avail: 500 };
+        let dirs = select_for_free(vec![(1, stat, target(&one).into_iter().chain(target(&two)).collect())], Watermark::Bytes(2000));
+        assert_eq!(vec!["b", "d"], dir_names(&dirs));
+        // Each filesystem is short on its own.
+        let dirs = select_for_free(vec![(1, stat, target(&one)), (2, stat, target(&two))], Watermark::Bytes(2000));
+        assert_eq!(vec!["a", "b", "c", "d"], dir_names(&dirs));
+    }
+
+    #[test]
+    fn test_include_exclude() {
+        let dir = make_test_dir("globs", &[("a.log", 30), ("b.log.1", 30), ("c.txt", 30),
+                                           ("logs/d.log", 30), ("logs/keep/e.log", 30), ("tmp/f.log", 30)]);
+        let names = |args: &[&str]| selected_names(&dir, &[&["-r", "-d", "1"], args].concat());
+
+        assert_eq!(vec!["a.log", "d.log", "e.log", "f.log"], names(&["--include", "*.log"]));
+        assert_eq!(vec!["d.log", "e.log"], names(&["--include", "logs/**"]));
+        assert_eq!(vec!["a.log", "d.log", "f.log"], names(&["--include", "*.log", "--exclude", "keep"]));
+        assert_eq!(vec!["a.log", "b.log.1", "c.txt", "f.log"], names(&["--exclude=logs"]));
+        assert_eq!(vec!["a.log", "b.log.1", "c.txt", "e.log"], names(&["--exclude", "logs/*.log", "--exclude", "tmp"]));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_regex() {
+        let dir = make_test_dir("regex", &[("app-2026-10-01.log.3", 30), ("app-2026-10-01.log", 30), ("old/app-2026-09-01.log.1", 30)]);
+        let names = |args: &[&str]| selected_names(&dir, &[&["-d", "1"], args].concat());
+
+        assert_eq!(vec!["app-2026-09-01.log.1", "app-2026-10-01.log.3"], names(&["-r", "--regex", r"\.log\.\d+$"]));
+        assert_eq!(vec!["app-2026-10-01.log.3"], names(&["-r", "--regex", r"^app-.*\.log\.\d+$"]));
+        assert_eq!(vec!["app-2026-10-01.log"], names(&["-r", "--exclude-regex", r"\d$"]));
+        assert_eq!(vec!["app-2026-10-01.log", "app-2026-10-01.log.3"], names(&["-r", "--exclude-regex", "^old$"]));
+        assert_eq!(vec!["old"], names(&["--remove-dir", "-d", "0", "--regex", "^o"]));
+        assert!(names(&["--remove-dir", "-d", "0", "--exclude-regex", "old"]).is_empty());
+    }
+
+    #[test]
+    fn test_ignore_file() {
+        let dir = make_test_dir("ignore", &[("a.log", 30), ("b.keep", 30), ("sub/c.log", 30), ("sub/d.log", 30),
+                                            ("sub/deep/e.log", 30), ("cache/f.log", 30), ("other/g.log", 30)]);
+        fs::write(dir.join(IGNORE_FILE), "*.keep\ncache/\n").unwrap();
+        fs::write(dir.join("sub").join(IGNORE_FILE), "*.log\n!d.log\n").unwrap();
+
+        let config = Config::parse_config(&argv(&dir, &["-r", "-d", "1"])).unwrap();
+        let dirs = Dir::get_target_files(&config).unwrap();
+        assert_eq!(vec!["a.log", "d.log", "g.log"], dir_names(&dirs));
+
+        let reason = |name: &str| dirs.iter().flat_map(|d| d.kept.iter())
+            .find(|k| k.entry.name == name).map(|k| k.reason.clone()).unwrap();
+        assert!(reason("b.keep").contains(&format!("{}:1", dir.join(IGNORE_FILE).display())));
+        assert!(reason("cache").contains(&format!("{}:2", dir.join(IGNORE_FILE).display())));
+        assert!(reason("e.log").contains(&format!("{}:1", dir.join("sub").join(IGNORE_FILE).display())));
+
+        assert_eq!(vec!["other"], selected_names(&dir, &["--remove-dir", "-d", "0"]));
+    }
+
+    #[test]
+    fn test_jobs() {
+        let mut files = Vec::new();
+        for i in 0..6 {
+            for j in 0..4 {
+                files.push((format!("d{}/e{}/f", i, j), 30));
+            }
+            files.push((format!("d{}/g", i), 30));
+        }
+        let files: Vec<(&str, u64)> = files.iter().map(|(name, age)| (name.as_str(), *age)).collect();
+        let dir = make_test_dir("jobs", &files);
+        let walk = |jobs: &str| -> Vec<String> {
+            let config = Config::parse_config(&argv(&dir, &["-r", "-d", "1", &format!("--jobs={}", jobs)])).unwrap();
+            Dir::get_target_files(&config).unwrap().iter().map(|d| d.rel_path.display().to_string()).collect()
+        };
+
+        // The same dirs in the same order, each dir before the dirs in it.
+        let one = walk("1");
+        assert_eq!(30, one.len());
+        assert_eq!(one, walk("8"));
+        for (i, rel) in one.iter().enumerate() {
+            if let Some(parent) = Path::new(rel).parent().filter(|parent| !parent.as_os_str().is_empty()) {
+                assert!(one[..i].iter().any(|r| Path::new(r) == parent));
+            }
+        }
+    }
+
+    #[test]
+    fn test_skipped() {
+        let dir = make_test_dir("skipped", &[("a/x.log", 30), ("c/y.log", 30)]);
+        // An ignore file that can not be read leaves its dir out.
+        fs::create_dir(dir.join("a").join(IGNORE_FILE)).unwrap();
+        let config = |extra: &[&str]| Config::parse_config(&argv(&dir, &[&["-r", "-d", "1"], extra].concat())).unwrap();
+
+        let dirs = Dir::get_target_files(&config(&[])).unwrap();
+        assert_eq!(vec!["y.log"], dir_names(&dirs));
+        let skipped: Vec<&Skipped> = dirs.iter().flat_map(|d| d.skipped.iter()).collect();
+        assert_eq!(1, skipped.len());
+        assert_eq!(dir.join("a"), skipped[0].path);
+        assert!(matches!(skipped[0].error, Error::Io { .. }));
+        assert!(skipped[0].reason().contains(IGNORE_FILE));
+
+        // Still reported when the size rules select nothing.
+        for extra in [["--max-total-size", "1G"], ["--min-free", "0%"]].iter() {
+            let dirs = Dir::get_target_files(&config(extra)).unwrap();
+            assert!(dirs.iter().all(|d| d.files.is_empty()));
+            assert_eq!(1, dirs.iter().map(|d| d.skipped.len()).sum::<usize>());
+        }
+
+        assert!(matches!(Dir::get_target_files(&config(&["--fail-fast"])), Err(Error::Io { .. })));
+    }
+
+    #[test]
+    fn test_filter() {
+        use crate::arg::ConfigBuilder;
+        use crate::filter::{self, Filter};
+        use crate::retention::Policy;
+        use crate::time::Cutoff;
+        use std::time::Duration;
+
+        let dir = make_test_dir("filter", &[("a.log", 30), ("b.log", 35), ("c.log", 40), ("new.log", 0)]);
+        let names = |builder: ConfigBuilder| {
+            config_names(&builder.target(&dir).older_than(Cutoff::Age(Duration::from_secs(3600))).build().unwrap())
+        };
+        let not_b = || filter::from_fn(|path: &Path, _: &Metadata| path.file_name() != Some(OsStr::new("b.log")));
+        let small = || filter::from_fn(|_: &Path, meta: &Metadata| meta.len() < 100);
+
+        assert_eq!(vec!["a.log", "b.log", "c.log"], names(ConfigBuilder::new()));
+        assert_eq!(vec!["a.log", "c.log"], names(ConfigBuilder::new().filter(not_b())));
+        assert_eq!(vec!["b.log"], names(ConfigBuilder::new().filter(not_b().not())));
+        assert_eq!(vec!["a.log", "c.log"], names(ConfigBuilder::new().filter(not_b().or(small()))));
+        assert!(names(ConfigBuilder::new().filter(not_b()).filter(small())).is_empty());
+        // The --keep-* rules still count the rejected files.
+        let keep_two = Policy { last: Some(2), ..Policy::default() };
+        assert_eq!(vec!["c.log"], names(ConfigBuilder::new().policy(keep_two).filter(not_b())));
+    }
+
+    #[test]
+    fn test_from_file() {
+        let dir = make_test_dir("from-file", &[("a.log", 30), ("b\nc.log", 30), ("new.log", 0), ("sub/d.log", 30), ("sub/e.txt", 30)]);
+        let list = dir.join("list");
+        fs::hard_link(dir.join("sub/d.log"), dir.join("sub/h.log")).unwrap();
+        // "a.log" is listed twice, once as "./a.log"; "h.log" is another
+        // name of "d.log".
+        let listed = ["a.log", "b\nc.log", "new.log", "sub", "sub/d.log", "sub/e.txt", "./a.log", "sub/h.log"];
+        let bytes: Vec<u8> = listed.iter().flat_map(|name| format!("{}/{}\0", dir.display(), name).into_bytes()).collect();
+        fs::write(&list, bytes).unwrap();
+
+        let argv = |args: &[&str]| {
+            let mut argv = vec![OsString::from("rm-old"), OsString::from("--from-file"), list.clone().into_os_string(), OsString::from("-d"), OsString::from("1")];
+            argv.extend(args.iter().map(OsString::from));
+            argv
+        };
+        let config = Config::parse_config(&argv(&["--null", "--exclude", "*.txt"])).unwrap();
+        let dirs = Dir::get_target_files(&config).unwrap();
+        let paths: Vec<String> = dirs.iter().rev()
+            .flat_map(|d| d.files.iter().map(move |e| d.get_path(&e.name).display().to_string())).collect();
+        assert_eq!(2, dirs.len());
+        assert_eq!(4, paths.len());
+        assert!(paths.contains(&format!("{}/b\nc.log", dir.display())));
+        assert!(paths[2..].contains(&format!("{}/sub/d.log", dir.display())));
+        assert!(paths[2..].contains(&format!("{}/sub/h.log", dir.display())));
+
+        // Without --null, the halves of the name with a newline do not exist.
+        let config = Config::parse_config(&argv(&[])).unwrap();
+        let dirs = Dir::get_target_files(&config).unwrap();
+        assert_eq!(2, dirs.iter().map(|d| d.skipped.len()).sum::<usize>());
+        let config = Config::parse_config(&argv(&["--fail-fast"])).unwrap();
+        assert!(Dir::get_target_files(&config).is_err());
+    }
+
+    #[test]
+    fn test_from_file_ignore() {
+        let dir = make_test_dir("from-file-ignore", &[("u/v/x.log", 30), ("u/v/y.txt", 30), ("u/v/z.txt", 30)]);
+        fs::write(dir.join("u").join(IGNORE_FILE), "*.log\nv/z.txt\n").unwrap();
+        let list = dir.join("list");
+        fs::write(&list, format!("{0}/u/v/x.log\n{0}/u/v/y.txt\n{0}/u/v/z.txt\n", dir.display())).unwrap();
+
+        // The rules of an ancestor dir protect the listed files below it.
+        let argv = [OsStr::new("rm-old"), OsStr::new("--from-file"), list.as_os_str(), OsStr::new("-d"), OsStr::new("1")];
+        let dirs = Dir::get_target_files(&Config::parse_config(&argv).unwrap()).unwrap();
+        assert_eq!(vec!["y.txt"], dir_names(&dirs));
+        let kept: Vec<&Kept> = dirs.iter().flat_map(|d| d.kept.iter()).collect();
+        assert_eq!(2, kept.len());
+        assert!(kept.iter().all(|k| k.reason.contains(&dir.join("u").join(IGNORE_FILE).display().to_string())));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_non_utf8_names() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = make_test_dir("non-utf8", &[]);
+        let latin1 = OsStr::from_bytes(b"caf\xe9.log");
+        let sub = dir.join(OsStr::from_bytes(b"r\xe9sum\xe9"));
+        fs::create_dir_all(&sub).unwrap();
+        let old = SystemTime::now() - std::time::Duration::from_secs(86400);
+        for path in [dir.join(latin1), sub.join("a.log")].iter() {
+            File::create(path).unwrap().set_modified(old).unwrap();
+        }
+
+        // The dir itself may be given with a non-UTF-8 name as well.
+        let args = vec![OsString::from("rm-old"), sub.clone().into_os_string(), OsString::from("-d"), OsString::from("1")];
+        let config = Config::parse_config(&args).unwrap();
+        assert_eq!(1, Dir::get_target_files(&config).unwrap().len());
+
+        let args = vec![OsString::from("rm-old"), dir.as_os_str().to_os_string(), OsString::from("-r"),
+                        OsString::from("-d"), OsString::from("1"), OsString::from("--include=*.log")];
+        let config = Config::parse_config(&args).unwrap();
+        let dirs = Dir::get_target_files(&config).unwrap();
+        let found: Vec<(&Dir, &Entry)> = dirs.iter().flat_map(|d| d.files.iter().map(move |e| (d, e))).collect();
+        assert_eq!(2, found.len());
+        let (top, entry) = found.iter().find(|(_, e)| e.name == latin1).unwrap();
+        assert_eq!("caf\u{fffd}.log", entry.display().to_string());
+        assert_eq!(dir.join(latin1), top.get_path(&entry.name));
+
+        // Removal goes through the same bytes.
+        let (d, entry) = found.iter().find(|(_, e)| e.name == "a.log").unwrap();
+        assert_eq!(Path::new(OsStr::from_bytes(b"r\xe9sum\xe9/a.log")), d.get_rel_path(&entry.name));
+        let expect = crate::unlink::Expect { dev: entry.dev, ino: entry.ino, field: TimeField::Mtime, cutoff: SystemTime::now() };
+        crate::unlink::remove(d.get_target_path(), &d.get_rel_path(&entry.name), &expect).unwrap();
+        assert!(!sub.join("a.log").exists());
+    }
+
+    #[test]
+    fn test_get_file_time() {
+        let path = Path::new("test_dir/one");
+        let meta = fs::metadata(path).unwrap();
+
+        assert_eq!(meta.modified().unwrap(), get_file_time(&meta, TimeField::Mtime, path).unwrap());
+        assert_eq!(meta.accessed().unwrap(), get_file_time(&meta, TimeField::Atime, path).unwrap());
+        assert!(get_file_time(&meta, TimeField::Ctime, path).is_ok());
+        match meta.created() {
+            Ok(btime)   => assert_eq!(btime, get_file_time(&meta, TimeField::Btime, path).unwrap()),
+            Err(_)      => assert!(get_file_time(&meta, TimeField::Btime, path).is_err()),
+        }
+    }
 }
\ No newline at end of file
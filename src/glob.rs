@@ -0,0 +1,188 @@
+/// A shell style wildcard pattern.
+///
+/// `*` matches any run of characters except `/`, `?` one character except
+/// `/`, `[a-z]`/`[!a-z]` one character of (not of) a set, and `**` as a
+/// whole path segment any number of directories, e.g. `logs/**/*.gz`.
+/// Elsewhere `**` is the same as `*`.
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    source: String,
+    tokens: Vec<Token>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Char(char),
+    Any,
+    Star,
+    // "**" at the end of the pattern: everything, including "/".
+    DoubleStar,
+    // "**/": zero or more whole directories.
+    DoubleStarSlash,
+    Class(Vec<(char, char)>, bool),
+}
+
+impl Pattern {
+    pub fn new(source: &str) -> Result<Pattern, String> {
+        let chars: Vec<char> = source.chars().collect();
+        let mut tokens = Vec::new();
+        let mut i = 0;
+
+        while i < chars.len() {
+            match chars[i] {
+                '*' if chars.get(i + 1) == Some(&'*')
+                    && (i == 0 || chars[i - 1] == '/')
+                    && matches!(chars.get(i + 2), None | Some('/')) => {
+                    if chars.get(i + 2).is_some() {
+                        tokens.push(Token::DoubleStarSlash);
+                        i += 3;
+                    } else {
+                        tokens.push(Token::DoubleStar);
+                        i += 2;
+                    }
+                    continue;
+                },
+                '*'     => tokens.push(Token::Star),
+                '?'     => tokens.push(Token::Any),
+                '['     => {
+                    let (class, next) = parse_class(&chars, i)
+                        .ok_or_else(|| format!("rm-old: Illegal pattern: {} (unclosed [)", source))?;
+                    tokens.push(class);
+                    i = next;
+                    continue;
+                },
+                '\\' if i + 1 < chars.len() => {
+                    tokens.push(Token::Char(chars[i + 1]));
+                    i += 2;
+                    continue;
+                },
+                c       => tokens.push(Token::Char(c)),
+            }
+            i += 1;
+        }
+
+        Ok(Pattern {
+            source: source.to_string(),
+            tokens,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Whether the pattern matches the whole of `path`.
+    pub fn matches(&self, path: &str) -> bool {
+        let chars: Vec<char> = path.chars().collect();
+        match_tokens(&self.tokens, &chars)
+    }
+}
+
+/// Parse "[...]" starting at `start`, returning the token and the index after "]".
+fn parse_class(chars: &[char], start: usize) -> Option<(Token, usize)> {
+    let mut i = start + 1;
+    let negated = matches!(chars.get(i), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+    let mut ranges = Vec::new();
+    let mut first = true;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == ']' && !first {
+            return Some((Token::Class(ranges, negated), i + 1));
+        }
+        first = false;
+        if chars.get(i + 1) == Some(&'-') && chars.get(i + 2).is_some_and(|&end| end != ']') {
+            ranges.push((c, chars[i + 2]));
+            i += 3;
+        } else {
+            ranges.push((c, c));
+            i += 1;
+        }
+    }
+    None
+}
+
+fn match_tokens(tokens: &[Token], path: &[char]) -> bool {
+    match tokens.first() {
+        None                        => path.is_empty(),
+        Some(Token::DoubleStar)     => true,
+        Some(Token::DoubleStarSlash)  => {
+            // Try the rest at the start of every directory level.
+            let mut rest = path;
+            loop {
+                if match_tokens(&tokens[1..], rest) {
+                    return true;
+                }
+                match rest.iter().position(|&c| c == '/') {
+                    Some(pos)   => rest = &rest[pos + 1..],
+                    None        => return false,
+                }
+            }
+        },
+        Some(Token::Star)           => {
+            for skip in 0..=path.len() {
+                if match_tokens(&tokens[1..], &path[skip..]) {
+                    return true;
+                }
+                if skip < path.len() && path[skip] == '/' {
+                    break;
+                }
+            }
+            false
+        },
+        Some(token)                 => {
+            let c = match path.first() {
+                Some(&c)    => c,
+                None        => return false,
+            };
+            let ok = match token {
+                Token::Char(expected)           => c == *expected,
+                Token::Any                      => c != '/',
+                Token::Class(ranges, negated)   => {
+                    c != '/' && ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != *negated
+                },
+                _                               => unreachable!(),
+            };
+            ok && match_tokens(&tokens[1..], &path[1..])
+        },
+    }
+}
+
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    fn matches(pattern: &str, path: &str) -> bool {
+        Pattern::new(pattern).unwrap().matches(path)
+    }
+
+    #[test]
+    fn test_wildcards() {
+        assert!(matches("*.log", "app.log"));
+        assert!(!matches("*.log", "app.log.1"));
+        assert!(!matches("*.log", "sub/app.log"));
+        assert!(matches("app-?.log", "app-1.log"));
+        assert!(matches("app-[0-9].log", "app-7.log"));
+        assert!(!matches("app-[!0-9].log", "app-7.log"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("\\*", "*"));
+        assert!(!matches("\\*", "a"));
+    }
+
+    #[test]
+    fn test_double_star() {
+        assert!(matches("**/*.gz", "a.gz"));
+        assert!(matches("**/*.gz", "x/y/a.gz"));
+        assert!(matches("logs/**/*.gz", "logs/a.gz"));
+        assert!(matches("logs/**/*.gz", "logs/2026/10/a.gz"));
+        assert!(!matches("logs/**/*.gz", "other/a.gz"));
+        assert!(matches("cache/**", "cache/x/y"));
+        assert!(!matches("cache/**", "cachex/y"));
+        assert!(matches("a**", "abc"));
+        assert!(!matches("a**", "a/c"));
+        assert!(Pattern::new("[abc").is_err());
+    }
+}
@@ -0,0 +1,206 @@
+use crate::error::Error;
+use crate::glob::Pattern;
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Name of the file protecting paths from removal, with gitignore syntax.
+pub const IGNORE_FILE: &str = ".rmoldignore";
+
+#[derive(Clone, Debug)]
+struct Rule {
+    pattern:    Pattern,
+    negated:    bool,
+    dir_only:   bool,
+    // Anchored rules match the path relative to `base`, the others match
+    // the base name at any depth.
+    anchored:   bool,
+    base:       String,
+    source:     String,
+}
+
+/// The rules of the ignore files from the target down to the dir being
+/// scanned. Rules of deeper files take precedence. Cloning is cheap: the
+/// stacks of sub dirs share the layers of their parent.
+#[derive(Clone, Debug, Default)]
+pub struct IgnoreStack {
+    top:    Option<Arc<Layer>>,
+}
+
+/// The rules of one ignore file, over the layers of the dirs above it.
+#[derive(Debug)]
+struct Layer {
+    rules:  Vec<Rule>,
+    parent: Option<Arc<Layer>>,
+}
+
+impl IgnoreStack {
+    pub fn new() -> IgnoreStack {
+        IgnoreStack::default()
+    }
+
+    /// Stack for the dir `path`, whose path relative to the target is `rel`:
+    /// these rules plus those of its ignore file, if any. Only the new rules
+    /// are read; those above are shared.
+    pub fn push_dir(&self, path: &Path, rel: &str) -> Result<IgnoreStack, Error> {
+        let file_path = path.join(IGNORE_FILE);
+        let text = match fs::read_to_string(&file_path) {
+            Ok(text)                                    => text,
+            Err(ref why) if why.kind() == ErrorKind::NotFound => return Ok(self.clone()),
+            Err(why)                                    => return Err(Error::io(&file_path, why)),
+        };
+
+        let mut rules = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let source = format!("{}:{}", file_path.display(), i + 1);
+            if let Some(rule) = parse_rule(line, rel, source).map_err(Error::IgnoreFile)? {
+                rules.push(rule);
+            }
+        }
+        Ok(self.push_rules(rules))
+    }
+
+    fn push_rules(&self, rules: Vec<Rule>) -> IgnoreStack {
+        if rules.is_empty() {
+            return self.clone();
+        }
+        IgnoreStack { top: Some(Arc::new(Layer { rules, parent: self.top.clone() })) }
+    }
+
+    /// The ignore file and line protecting the entry at `rel` (relative to
+    /// the target), or None if it may be removed.
+    pub fn protected_by(&self, rel: &str, is_dir: bool) -> Option<&str> {
+        let name = rel.rsplit('/').next().unwrap_or(rel);
+        let mut layer = self.top.as_deref();
+        while let Some(current) = layer {
+            for rule in current.rules.iter().rev() {
+                if rule.dir_only && !is_dir {
+                    continue;
+                }
+                let matched = if rule.anchored {
+                    let sub = if rule.base.is_empty() {
+                        Some(rel)
+                    } else {
+                        rel.strip_prefix(rule.base.as_str()).and_then(|rest| rest.strip_prefix('/'))
+                    };
+                    sub.is_some_and(|sub| rule.pattern.matches(sub))
+                } else {
+                    rule.pattern.matches(name)
+                };
+                if matched {
+                    return if rule.negated { None } else { Some(&rule.source) };
+                }
+            }
+            layer = current.parent.as_deref();
+        }
+        None
+    }
+}
+
+fn parse_rule(line: &str, base: &str, source: String) -> Result<Option<Rule>, String> {
+    let mut line = line.trim_end();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+
+    // "\!" and "\#" stand for a leading "!" or "#".
+    let negated = line.starts_with('!');
+    if negated || line.starts_with("\\!") || line.starts_with("\\#") {
+        line = &line[1..];
+    }
+    let dir_only = line.ends_with('/');
+    if dir_only {
+        line = &line[..line.len() - 1];
+    }
+    // A slash at the start or in the middle ties the pattern to the dir of
+    // the ignore file.
+    let anchored = line.contains('/');
+    let line = line.strip_prefix('/').unwrap_or(line);
+    if line.is_empty() {
+        return Ok(None);
+    }
+
+    let pattern = Pattern::new(line).map_err(|err_msg| format!("{}: {}", source, err_msg))?;
+    Ok(Some(Rule {
+        pattern,
+        negated,
+        dir_only,
+        anchored,
+        base:   base.to_string(),
+        source: format!("{} ({})", source, line),
+    }))
+}
+
+/// Whether `path` holds an ignore file at any depth.
+pub fn contains_ignore_file(path: &Path) -> bool {
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(_)      => return false,
+    };
+    for entry in entries.flatten() {
+        if entry.file_name() == IGNORE_FILE {
+            return true;
+        }
+        if entry.file_type().is_ok_and(|t| t.is_dir()) && contains_ignore_file(&entry.path()) {
+            return true;
+        }
+    }
+    false
+}
+
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    fn stack(files: &[(&str, &str)]) -> IgnoreStack {
+        let mut stack = IgnoreStack::new();
+        for (base, text) in files.iter() {
+            let rules = text.lines().enumerate()
+                .filter_map(|(i, line)| parse_rule(line, base, format!("{}:{}", base, i + 1)).unwrap())
+                .collect();
+            stack = stack.push_rules(rules);
+        }
+        stack
+    }
+
+    #[test]
+    fn test_protected_by() {
+        let stack = stack(&[("", "# comment\n*.keep\n!important.keep\n/top.log\ncache/\nlogs/**/*.gz\n"),
+                            ("sub", "!b.keep\n/local\n")]);
+
+        assert!(stack.protected_by("a.keep", false).is_some());
+        assert!(stack.protected_by("x/y/a.keep", false).is_some());
+        assert!(stack.protected_by("important.keep", false).is_none());
+        assert!(stack.protected_by("top.log", false).is_some());
+        assert!(stack.protected_by("sub/top.log", false).is_none());
+        assert!(stack.protected_by("cache", true).is_some());
+        assert!(stack.protected_by("cache", false).is_none());
+        assert!(stack.protected_by("logs/2026/a.gz", false).is_some());
+        assert!(stack.protected_by("sub/logs/a.gz", false).is_none());
+        assert!(stack.protected_by("sub/b.keep", false).is_none());
+        assert!(stack.protected_by("sub/local", false).is_some());
+        assert!(stack.protected_by("local", false).is_none());
+
+        assert_eq!(Some("sub:2 (local)"), stack.protected_by("sub/local", true));
+        assert_eq!(Some(":2 (*.keep)"), stack.protected_by("sub/c.keep", true));
+    }
+
+    #[test]
+    fn test_shared_layers() {
+        let parent = stack(&[("", "*.keep\n")]);
+        // A dir without rules of its own shares the stack above.
+        let same = parent.push_rules(Vec::new());
+        assert!(Arc::ptr_eq(parent.top.as_ref().unwrap(), same.top.as_ref().unwrap()));
+
+        let rule = parse_rule("*.log", "sub", "sub:1".to_string()).unwrap().unwrap();
+        let child = parent.push_rules(vec![rule]);
+        let child_top = child.top.as_ref().unwrap();
+        assert_eq!(1, child_top.rules.len());
+        assert!(Arc::ptr_eq(parent.top.as_ref().unwrap(), child_top.parent.as_ref().unwrap()));
+        assert!(child.protected_by("sub/a.log", false).is_some());
+        assert!(parent.protected_by("sub/a.log", false).is_none());
+    }
+}
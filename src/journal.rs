@@ -0,0 +1,288 @@
+//! A journal of the files moved away by --move-to or --trash, so that
+//! `rm-old restore` can put them back.
+//!
+//! One line per file, tab separated: the time of the move, the action, the
+//! original path, the destination, the size, the mtime and the mode (octal).
+//! Paths are percent-encoded, so any file name fits on one line.
+
+use crate::error::Error;
+use crate::pathenc;
+use crate::relocate;
+use crate::time;
+use crate::trash;
+
+use std::env;
+use std::fs::{self, File, Metadata};
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+const HEADER: &str = "# rm-old journal 1\n# time\taction\toriginal\tdestination\tsize\tmtime\tmode\n";
+
+/// How a file was moved away.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Move,
+    Trash,
+}
+
+impl Action {
+    pub fn parse(s: &str) -> Result<Action, String> {
+        match s {
+            "move"  => Ok(Action::Move),
+            "trash" => Ok(Action::Trash),
+            _       => Err(format!("unknown action: {}", s)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Move    => "move",
+            Action::Trash   => "trash",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    pub time:           SystemTime,
+    pub action:         Action,
+    pub original:       PathBuf,
+    pub destination:    PathBuf,
+    pub size:           u64,
+    pub mtime:          SystemTime,
+    pub mode:           u32,
+}
+
+impl Record {
+    /// Record of `original`, whose metadata before the move was `meta`.
+    pub fn new(action: Action, original: &Path, destination: &Path, meta: &Metadata) -> Record {
+        Record {
+            time:           SystemTime::now(),
+            action,
+            original:       absolute(original),
+            destination:    absolute(destination),
+            size:           meta.len(),
+            mtime:          meta.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            mode:           mode(meta),
+        }
+    }
+
+    fn to_line(&self) -> String {
+        format!("{}\t{}\t{}\t{}\t{}\t{}\t{:o}\n",
+                time::format_datetime(self.time),
+                self.action.name(),
+                pathenc::encode_path(&self.original),
+                pathenc::encode_path(&self.destination),
+                self.size,
+                time::format_datetime(self.mtime),
+                self.mode)
+    }
+
+    fn parse(line: &str) -> Result<Record, String> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 7 {
+            return Err(format!("expected 7 fields, found {}", fields.len()));
+        }
+        let path = |s: &str| pathenc::decode_path(s).ok_or_else(|| format!("bad path: {}", s));
+        Ok(Record {
+            time:           time::parse_datetime(fields[0])?,
+            action:         Action::parse(fields[1])?,
+            original:       path(fields[2])?,
+            destination:    path(fields[3])?,
+            size:           fields[4].parse().map_err(|_| format!("bad size: {}", fields[4]))?,
+            mtime:          time::parse_datetime(fields[5])?,
+            mode:           u32::from_str_radix(fields[6], 8).map_err(|_| format!("bad mode: {}", fields[6]))?,
+        })
+    }
+}
+
+/// A journal being written. Each record is flushed as it is added, so the
+/// journal of an interrupted run is still complete up to that point.
+pub struct Journal {
+    path:   PathBuf,
+    file:   File,
+}
+
+impl Journal {
+    /// Start a new journal at `path`, creating its parent dirs.
+    pub fn create(path: &Path) -> Result<Journal, Error> {
+        let err = |why: io::Error| Error::io(path, why);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(err)?;
+        }
+        let mut file = File::options().write(true).create_new(true).open(path).map_err(err)?;
+        file.write_all(HEADER.as_bytes()).map_err(err)?;
+        Ok(Journal {
+            path: path.to_path_buf(),
+            file,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn add(&mut self, record: &Record) -> Result<(), Error> {
+        self.file.write_all(record.to_line().as_bytes())
+            .and_then(|_| self.file.sync_data())
+            .map_err(|why| Error::io(&self.path, why))
+    }
+}
+
+/// `$XDG_DATA_HOME/rm-old/journal/<time>-<pid>.tsv`, a new journal per run.
+pub fn default_path(now: SystemTime) -> Result<PathBuf, Error> {
+    let data_home = trash::data_home()?;
+    let stamp: String = time::format_datetime(now).chars().filter(|c| *c != '-' && *c != ':').collect();
+    Ok(data_home.join("rm-old/journal").join(format!("{}-{}.tsv", stamp, std::process::id())))
+}
+
+/// Read all the records of the journal at `path`.
+pub fn read(path: &Path) -> Result<Vec<Record>, Error> {
+    let text = fs::read_to_string(path).map_err(|why| Error::io(path, why))?;
+    let mut records = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let record = Record::parse(line).map_err(|err_msg| {
+            Error::io(path, io::Error::new(ErrorKind::InvalidData, format!("line {}: {}", i + 1, err_msg)))
+        })?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// What happened to a record given to `restore`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Outcome {
+    Restored,
+    /// Something is at the original path again; it is left alone.
+    Conflict,
+    /// The destination is gone.
+    Missing,
+}
+
+/// Move the file of `record` back to its original path, unless that would
+/// overwrite something, even something put there while restoring. With
+/// `dry_run` only the outcome is worked out.
+pub fn restore(record: &Record, dry_run: bool) -> io::Result<Outcome> {
+    if fs::symlink_metadata(&record.original).is_ok() {
+        return Ok(Outcome::Conflict);
+    }
+    if fs::symlink_metadata(&record.destination).is_err() {
+        return Ok(Outcome::Missing);
+    }
+    if dry_run {
+        return Ok(Outcome::Restored);
+    }
+
+    if let Some(parent) = record.original.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    match relocate::move_path_noreplace(&record.destination, &record.original) {
+        Ok(())                                                  => {},
+        Err(ref why) if why.kind() == io::ErrorKind::AlreadyExists => return Ok(Outcome::Conflict),
+        Err(why)                                                => return Err(why),
+    }
+    if record.action == Action::Trash {
+        if let Some(info) = trash::info_path(&record.destination) {
+            let _ = fs::remove_file(info);
+        }
+    }
+    Ok(Outcome::Restored)
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        return path.to_path_buf();
+    }
+    match env::current_dir() {
+        Ok(dir) => dir.join(path),
+        Err(_)  => path.to_path_buf(),
+    }
+}
+
+#[cfg(unix)]
+fn mode(meta: &Metadata) -> u32 {
+    use std::os::unix::fs::MetadataExt;
+    meta.mode()
+}
+
+#[cfg(not(unix))]
+fn mode(meta: &Metadata) -> u32 {
+    if meta.permissions().readonly() { 0o444 } else { 0o644 }
+}
+
+
+#[cfg(test)]
+mod test{
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn test_record_line() {
+        let record = Record {
+            time:           time::parse_datetime("2026-10-18T12:00:00Z").unwrap(),
+            action:         Action::Trash,
+            original:       PathBuf::from("/data/a\tb.log"),
+            destination:    PathBuf::from("/home/u/.local/share/Trash/files/a\tb.log"),
+            size:           1000,
+            mtime:          time::parse_datetime("2026-01-02T03:04:05Z").unwrap(),
+            mode:           0o100644,
+        };
+        let line = record.to_line();
+        assert_eq!("2026-10-18T12:00:00Z\ttrash\t/data/a%09b.log\t/home/u/.local/share/Trash/files/a%09b.log\t1000\t2026-01-02T03:04:05Z\t100644\n",
+                   line);
+        assert_eq!(record, Record::parse(line.trim_end()).unwrap());
+        assert!(Record::parse("2026-10-18T12:00:00Z\tmove\t/a").is_err());
+    }
+
+    #[test]
+    fn test_restore() {
+        let base = TempDir::new("journal");
+        fs::create_dir_all(base.join("data/sub")).unwrap();
+        fs::write(base.join("data/sub/a"), "a").unwrap();
+        fs::write(base.join("data/b"), "b").unwrap();
+
+        let mut journal = Journal::create(&base.join("journal/run.tsv")).unwrap();
+        for name in ["sub/a", "b"].iter() {
+            let src = base.join("data").join(name);
+            let dst = base.join("archive").join(name);
+            let meta = fs::symlink_metadata(&src).unwrap();
+            fs::create_dir_all(dst.parent().unwrap()).unwrap();
+            relocate::move_path(&src, &dst).unwrap();
+            journal.add(&Record::new(Action::Move, &src, &dst, &meta)).unwrap();
+        }
+        // The original of "b" is recreated.
+        fs::write(base.join("data/b"), "new").unwrap();
+        fs::remove_dir(base.join("data/sub")).unwrap();
+
+        let records = read(journal.path()).unwrap();
+        assert_eq!(2, records.len());
+        assert_eq!(base.join("data/sub/a"), records[0].original);
+        assert_eq!(Outcome::Restored, restore(&records[0], true).unwrap());
+        assert!(!base.join("data/sub/a").exists());
+        assert_eq!(Outcome::Restored, restore(&records[0], false).unwrap());
+        assert_eq!("a", fs::read_to_string(base.join("data/sub/a")).unwrap());
+        assert_eq!(Outcome::Conflict, restore(&records[1], false).unwrap());
+        assert_eq!("new", fs::read_to_string(base.join("data/b")).unwrap());
+        assert!(base.join("archive/b").exists());
+
+        fs::remove_file(base.join("data/b")).unwrap();
+        fs::remove_file(base.join("archive/b")).unwrap();
+        assert_eq!(Outcome::Missing, restore(&records[1], false).unwrap());
+
+        // A bad line is reported with the journal path and its line number.
+        fs::write(base.join("bad.tsv"), format!("{}2026-10-18T12:00:00Z\tmove\t/a\n", HEADER)).unwrap();
+        match read(&base.join("bad.tsv")) {
+            Err(Error::Io { path, source }) => {
+                assert_eq!(base.join("bad.tsv"), path);
+                assert_eq!(ErrorKind::InvalidData, source.kind());
+                assert!(source.to_string().starts_with("line 3: "));
+            },
+            other                           => panic!("{:?}", other.map(|records| records.len())),
+        }
+        assert!(matches!(read(&base.join("none.tsv")), Err(Error::Io { source, .. }) if source.kind() == ErrorKind::NotFound));
+    }
+}
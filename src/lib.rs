@@ -1,2 +1,26 @@
+pub mod action;
+pub mod archive;
 pub mod arg;
-pub mod fs;
\ No newline at end of file
+pub mod disk;
+pub mod error;
+pub mod filter;
+pub mod fs;
+pub mod glob;
+pub mod ignore;
+pub mod journal;
+pub mod matcher;
+pub mod pathenc;
+pub mod plan;
+pub mod relocate;
+pub mod report;
+pub mod retention;
+pub mod scan;
+pub mod size;
+pub mod time;
+pub mod trash;
+pub mod unlink;
+
+#[cfg(test)]
+mod testutil;
+
+pub use crate::error::Error;
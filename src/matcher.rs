@@ -0,0 +1,55 @@
+/// A regular expression matched against paths relative to the target.
+/// Only available with the "regex" feature; otherwise `new` fails.
+#[derive(Clone, Debug)]
+pub struct NameRegex {
+    #[cfg(feature = "regex")]
+    regex:  regex::Regex,
+    source: String,
+}
+
+impl NameRegex {
+    #[cfg(feature = "regex")]
+    pub fn new(source: &str) -> Result<NameRegex, String> {
+        match regex::Regex::new(source) {
+            Ok(regex)   => Ok(NameRegex { regex, source: source.to_string() }),
+            Err(why)    => Err(format!("rm-old: Illegal regex: {}\n{}", source, why)),
+        }
+    }
+
+    #[cfg(not(feature = "regex"))]
+    pub fn new(source: &str) -> Result<NameRegex, String> {
+        Err(format!("rm-old: Illegal regex: {} (built without the \"regex\" feature)", source))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Whether the regex matches anywhere in `path`; anchor it with ^ and $
+    /// to match the whole path.
+    #[cfg(feature = "regex")]
+    pub fn is_match(&self, path: &str) -> bool {
+        self.regex.is_match(path)
+    }
+
+    #[cfg(not(feature = "regex"))]
+    pub fn is_match(&self, _path: &str) -> bool {
+        false
+    }
+}
+
+
+#[cfg(all(test, feature = "regex"))]
+mod test{
+    use super::*;
+
+    #[test]
+    fn test_is_match() {
+        let regex = NameRegex::new(r"^app-\d{4}-\d{2}-\d{2}\.log\.\d+$").unwrap();
+        assert!(regex.is_match("app-2026-10-01.log.3"));
+        assert!(!regex.is_match("app-2026-10-01.log"));
+        assert!(!regex.is_match("sub/app-2026-10-01.log.3"));
+        assert!(NameRegex::new(r"\.log$").unwrap().is_match("sub/app.log"));
+        assert!(NameRegex::new("(unclosed").is_err());
+    }
+}
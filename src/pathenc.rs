@@ -0,0 +1,72 @@
+//! Paths as bytes and as percent-encoded text, for the files that record
+//! paths: trash info files, journals, plans and --from-file lists.
+
+use std::path::{Path, PathBuf};
+
+/// Percent-encode a path (RFC 2396 escaping, "/" kept), as the trash spec
+/// asks and as the journal and plans store paths.
+pub fn encode_path(path: &Path) -> String {
+    let mut out = String::new();
+    for &b in path_bytes(path).iter() {
+        if b.is_ascii_alphanumeric() || b"/-_.!~*'()".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+/// Reverse of `encode_path`.
+pub fn decode_path(s: &str) -> Option<PathBuf> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    path_from_bytes(out)
+}
+
+#[cfg(unix)]
+pub fn path_from_bytes(bytes: Vec<u8>) -> Option<PathBuf> {
+    use std::os::unix::ffi::OsStringExt;
+    Some(PathBuf::from(std::ffi::OsString::from_vec(bytes)))
+}
+
+#[cfg(not(unix))]
+pub fn path_from_bytes(bytes: Vec<u8>) -> Option<PathBuf> {
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+#[cfg(unix)]
+pub fn path_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+pub fn path_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn test_encode_path() {
+        assert_eq!("/tmp/a%20b/%E3%81%82.txt", encode_path(Path::new("/tmp/a b/あ.txt")));
+        assert_eq!("/x/100%25", encode_path(Path::new("/x/100%")));
+        assert_eq!(Some(PathBuf::from("/tmp/a b/あ.txt")), decode_path("/tmp/a%20b/%E3%81%82.txt"));
+        assert_eq!(None, decode_path("/x/100%2"));
+    }
+}
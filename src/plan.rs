@@ -0,0 +1,259 @@
+//! The two-phase workflow: `rm-old plan` writes the removal candidates to a
+//! JSON file to be reviewed, and `rm-old apply` later removes exactly those,
+//! skipping any that were replaced or modified in the meantime.
+
+use crate::arg::{Config, TimeField};
+use crate::error::Error;
+use crate::fs::{self as rm_fs, Dir};
+use crate::pathenc;
+use crate::time;
+use crate::unlink::Expect;
+
+use serde::{Deserialize, Serialize};
+
+use std::fs;
+use std::io::{self, ErrorKind, Write};
+use std::path::{self, Path, PathBuf};
+use std::time::SystemTime;
+
+const VERSION: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Plan {
+    pub version:        u32,
+    pub created:        String,
+    /// Timestamp the ages are taken from, as --time-field.
+    pub time_field:     String,
+    /// Entries must still be older than this when the plan is applied.
+    pub older_than:     String,
+    /// Whether the entries are dirs, removed with their contents.
+    pub remove_dir:     bool,
+    pub entries:        Vec<PlanEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlanEntry {
+    /// Absolute path.
+    pub path:       String,
+    /// The target the entry was found in, trusted as given, and the path
+    /// of the entry under it, walked without following symlinks on apply.
+    pub root:       String,
+    pub rel_path:   String,
+    /// Set when the path is not UTF-8: `path`, `root` and `rel_path` are
+    /// then percent-encoded, so that the name is kept as is.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub encoded:    bool,
+    pub size:       u64,
+    pub device:     u64,
+    pub inode:      u64,
+    /// The timestamp used for the age, RFC3339.
+    pub time:       String,
+}
+
+impl PlanEntry {
+    /// The path of the entry, or None if the plan holds a bad encoding.
+    pub fn path(&self) -> Option<PathBuf> {
+        self.decode(&self.path)
+    }
+
+    /// The target and the path under it, or None if the plan holds a bad
+    /// encoding.
+    pub fn root_and_rel_path(&self) -> Option<(PathBuf, PathBuf)> {
+        Some((self.decode(&self.root)?, self.decode(&self.rel_path)?))
+    }
+
+    fn decode(&self, text: &str) -> Option<PathBuf> {
+        if self.encoded {
+            pathenc::decode_path(text)
+        } else {
+            Some(PathBuf::from(text))
+        }
+    }
+
+    /// The path as shown to the user.
+    pub fn display(&self) -> String {
+        match self.path() {
+            Some(path)  => path.display().to_string(),
+            None        => self.path.clone(),
+        }
+    }
+}
+
+impl Plan {
+    /// Plan the removal of the files selected in `dirs` by `config`, with
+    /// the age cutoff as of `now`.
+    pub fn new(dirs: &[Dir], config: &Config, now: SystemTime) -> Result<Plan, Error> {
+        let mut entries = Vec::new();
+        for dir in dirs.iter().rev() {
+            for f in dir.get_files().iter() {
+                let root = dir.get_target_path();
+                let root = path::absolute(root).map_err(|why| Error::io(root, why))?;
+                let rel_path = dir.get_rel_path(&f.name);
+                let path = root.join(&rel_path);
+                // Verbatim for review, unless that would lose bytes.
+                let encoded = path.to_str().is_none();
+                let text = |path: &Path| if encoded { pathenc::encode_path(path) } else { path.to_string_lossy().into_owned() };
+                entries.push(PlanEntry {
+                    path:       text(&path),
+                    root:       text(&root),
+                    rel_path:   text(&rel_path),
+                    encoded,
+                    size:       f.size,
+                    device:     f.dev,
+                    inode:      f.ino,
+                    time:       time::format_datetime(f.time),
+                });
+            }
+        }
+        Ok(Plan {
+            version:    VERSION,
+            created:    time::format_datetime(now),
+            time_field: config.time_field().name().to_string(),
+            older_than: time::format_datetime(config.time_window(now)?.older),
+            remove_dir: config.remove_dir(),
+            entries,
+        })
+    }
+
+    /// Write the plan to `path`, or to stdout for "-".
+    pub fn write(&self, path: &str) -> Result<(), Error> {
+        let err = |why: io::Error| Error::io(Path::new(path), why);
+        let mut json = serde_json::to_string_pretty(self).map_err(|why| err(why.into()))?;
+        json.push('\n');
+        let result = if path == "-" {
+            io::stdout().write_all(json.as_bytes())
+        } else {
+            fs::write(path, json)
+        };
+        result.map_err(err)
+    }
+
+    pub fn read(path: &str) -> Result<Plan, Error> {
+        let err = |why: io::Error| Error::io(Path::new(path), why);
+        let json = fs::read_to_string(path).map_err(err)?;
+        let plan: Plan = serde_json::from_str(&json).map_err(|why| err(why.into()))?;
+        if plan.version != VERSION {
+            return Err(err(io::Error::new(ErrorKind::InvalidData, format!("unknown plan version {}", plan.version))));
+        }
+        Ok(plan)
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.entries.iter().map(|entry| entry.size).sum()
+    }
+
+    /// Check that `entry` is still the file that was planned and still old
+    /// enough, returning what it must still be when removed, or why it must
+    /// be skipped.
+    pub fn verify(&self, entry: &PlanEntry) -> Result<Expect, String> {
+        let path = entry.path().ok_or_else(|| "bad path".to_string())?;
+        let path = path.as_path();
+        let meta = match fs::symlink_metadata(path) {
+            Ok(meta)                                            => meta,
+            Err(ref why) if why.kind() == io::ErrorKind::NotFound => return Err("missing".to_string()),
+            Err(why)                                            => return Err(why.to_string()),
+        };
+        if meta.is_dir() != self.remove_dir {
+            return Err("file type changed".to_string());
+        }
+        if rm_fs::get_file_id(&meta) != (entry.device, entry.inode) {
+            return Err("replaced, device/inode changed".to_string());
+        }
+
+        let field = TimeField::parse(&self.time_field).map_err(|err| err.to_string())?;
+        let older_than = time::parse_datetime(&self.older_than)?;
+        let file_time = rm_fs::get_file_time(&meta, field, path).map_err(|err| err.to_string())?;
+        if file_time >= older_than {
+            return Err(format!("{} is now {}, not older than {}", field.name(), time::format_datetime(file_time), self.older_than));
+        }
+        Ok(Expect {
+            dev:    entry.device,
+            ino:    entry.inode,
+            field,
+            cutoff: older_than,
+        })
+    }
+}
+
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+    use crate::testutil::TempDir;
+    use std::fs::File;
+    use std::time::Duration;
+
+    #[test]
+    fn test_plan() {
+        let base = TempDir::new("plan");
+        let old = SystemTime::now() - Duration::from_secs(10 * 86400);
+        for name in ["a", "b", "c", "d"].iter() {
+            let file = File::create(base.join(name)).unwrap();
+            file.set_len(100).unwrap();
+            file.set_modified(old).unwrap();
+        }
+
+        let args = vec!["rm-old".to_string(), base.to_str().unwrap().to_string(), "-d".to_string(), "1".to_string()];
+        let config = Config::parse_config(&args).unwrap();
+        let dirs = Dir::get_target_files(&config).unwrap();
+        let plan = Plan::new(&dirs, &config, SystemTime::now()).unwrap();
+        assert_eq!(4, plan.entries.len());
+        assert_eq!(400, plan.total_size());
+
+        let plan_path = base.join("plan.json");
+        plan.write(plan_path.to_str().unwrap()).unwrap();
+        let plan = Plan::read(plan_path.to_str().unwrap()).unwrap();
+        // UTF-8 paths are written as they are.
+        let json = fs::read_to_string(&plan_path).unwrap();
+        assert!(json.contains(&format!("\"{}\"", base.join("a").display())));
+        assert!(!json.contains("encoded"));
+
+        // "a" is untouched, "b" replaced, "c" modified and "d" gone.
+        File::create(base.join("b.new")).unwrap().set_modified(old).unwrap();
+        fs::rename(base.join("b.new"), base.join("b")).unwrap();
+        File::options().write(true).open(base.join("c")).unwrap().set_modified(SystemTime::now()).unwrap();
+        fs::remove_file(base.join("d")).unwrap();
+
+        let entry = |name: &str| plan.entries.iter().find(|e| e.path.ends_with(&format!("/{}", name))).unwrap();
+        assert!(plan.verify(entry("a")).is_ok());
+        assert!(plan.verify(entry("b")).unwrap_err().contains("inode"));
+        assert!(plan.verify(entry("c")).unwrap_err().contains("mtime"));
+        assert_eq!("missing", plan.verify(entry("d")).unwrap_err());
+
+        fs::write(base.join("bad.json"), "{").unwrap();
+        assert!(matches!(Plan::read(base.join("bad.json").to_str().unwrap()),
+                         Err(Error::Io { source, .. }) if source.kind() == ErrorKind::UnexpectedEof));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_non_utf8_path() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let base = TempDir::new("plan-latin1");
+        let latin1 = base.join(OsStr::from_bytes(b"caf\xe9.log"));
+        File::create(&latin1).unwrap().set_modified(SystemTime::now() - Duration::from_secs(10 * 86400)).unwrap();
+
+        let args = vec!["rm-old".to_string(), base.to_str().unwrap().to_string(), "-d".to_string(), "1".to_string()];
+        let config = Config::parse_config(&args).unwrap();
+        let plan = Plan::new(&Dir::get_target_files(&config).unwrap(), &config, SystemTime::now()).unwrap();
+        let plan_path = base.join("plan.json");
+        plan.write(plan_path.to_str().unwrap()).unwrap();
+        let plan = Plan::read(plan_path.to_str().unwrap()).unwrap();
+
+        // The name comes back byte for byte, so the file is found again.
+        assert_eq!(1, plan.entries.len());
+        assert!(plan.entries[0].encoded && plan.entries[0].path.ends_with("/caf%E9.log"));
+        assert_eq!(Some(latin1.clone()), plan.entries[0].path());
+        let expect = plan.verify(&plan.entries[0]).unwrap();
+        let (root, rel_path) = plan.entries[0].root_and_rel_path().unwrap();
+        assert_eq!((base.to_path_buf(), PathBuf::from(OsStr::from_bytes(b"caf\xe9.log"))), (root.clone(), rel_path.clone()));
+        crate::unlink::remove(&root, &rel_path, &expect).unwrap();
+        assert!(!latin1.exists());
+    }
+}
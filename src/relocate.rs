@@ -0,0 +1,358 @@
+use crate::error::Error;
+use crate::unlink::{self, Expect, RemoveError};
+
+use std::fs::{self, File, FileTimes};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// What to do when the destination of a move already exists.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Conflict {
+    Skip,
+    Overwrite,
+    /// Add a numeric suffix: "name.1", "name.2", ...
+    Rename,
+}
+
+impl Conflict {
+    pub fn parse(s: &str) -> Result<Conflict, Error> {
+        match s {
+            "skip"      => Ok(Conflict::Skip),
+            "overwrite" => Ok(Conflict::Overwrite),
+            "rename"    => Ok(Conflict::Rename),
+            _           => Err(Error::InvalidArgument(format!("rm-old --on-conflict: Illegal value: {} (skip, overwrite or rename)", s))),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Conflict::Skip      => "skip",
+            Conflict::Overwrite => "overwrite",
+            Conflict::Rename    => "rename",
+        }
+    }
+}
+
+/// Move the entry at `rel` under `root` to `dst`, if it is still what
+/// `expect` says, creating the parent dirs of `dst` and settling an existing
+/// `dst` by `conflict`. Returns where it went, or None if skipped. With
+/// skip and rename nothing at the destination is ever replaced, even if it
+/// appears while moving. See `move_entry` for how the entry is checked.
+pub fn move_into(root: &Path, rel: &Path, expect: &Expect, dst: &Path, conflict: Conflict) -> Result<Option<PathBuf>, RemoveError> {
+    if let Some(parent) = dst.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    if conflict == Conflict::Overwrite {
+        if fs::symlink_metadata(dst).is_ok() {
+            remove_path(dst)?;
+        }
+        move_entry(root, rel, expect, dst, true)?;
+        return Ok(Some(dst.to_path_buf()));
+    }
+    let mut dest = dst.to_path_buf();
+    for n in 1.. {
+        match move_entry(root, rel, expect, &dest, false) {
+            Ok(())                                                                      => break,
+            Err(RemoveError::Io(ref why)) if why.kind() == io::ErrorKind::AlreadyExists => {
+                if conflict == Conflict::Skip {
+                    return Ok(None);
+                }
+                let mut name = dst.file_name().unwrap_or_default().to_os_string();
+                name.push(format!(".{}", n));
+                dest = dst.with_file_name(name);
+            },
+            Err(err)                                                                    => return Err(err),
+        }
+    }
+    Ok(Some(dest))
+}
+
+/// Move the entry at `rel` under `root` to `dst` if it is still what
+/// `expect` says. It is checked and renamed through the fd of its dir, as
+/// `unlink::remove` does, so a dir on the way swapped for a symlink is not
+/// followed. Across filesystems it is copied from its path next to `dst`
+/// and put in place, and the original is then removed by
+/// `unlink::remove`; if that finds it changed, the copy is dropped.
+/// Unless `replace`, nothing at `dst` is replaced.
+pub fn move_entry(root: &Path, rel: &Path, expect: &Expect, dst: &Path, replace: bool) -> Result<(), RemoveError> {
+    match unlink::rename(root, rel, expect, dst, replace) {
+        Err(RemoveError::Io(ref why)) if is_cross_device(why)   => {
+            let tmp = temp_path(dst);
+            let copied = copy_tree(&root.join(rel), &tmp)
+                .and_then(|_| if replace { fs::rename(&tmp, dst) } else { rename_noreplace(&tmp, dst) });
+            if let Err(why) = copied {
+                let _ = remove_path(&tmp);
+                return Err(RemoveError::Io(why));
+            }
+            unlink::remove(root, rel, expect).inspect_err(|_| {
+                let _ = remove_path(dst);
+            })
+        },
+        result                                                  => result,
+    }
+}
+
+/// Move `src` to `dst` (which must not exist). A rename is tried first; across
+/// filesystems the file or dir tree is copied with its permissions and
+/// timestamps, then the original is removed.
+pub fn move_path(src: &Path, dst: &Path) -> io::Result<()> {
+    match fs::rename(src, dst) {
+        Ok(())                                          => Ok(()),
+        Err(ref why) if is_cross_device(why)            => {
+            if let Err(why) = copy_tree(src, dst) {
+                // Leave no half copied tree behind.
+                let _ = remove_path(dst);
+                return Err(why);
+            }
+            remove_path(src)
+        },
+        Err(why)                                        => Err(why),
+    }
+}
+
+/// Move `src` to `dst` like `move_path`, but fail with `AlreadyExists`
+/// rather than replace anything at `dst`, even something that appears while
+/// moving. Across filesystems the copy is made next to `dst` and then put
+/// in place the same way.
+pub fn move_path_noreplace(src: &Path, dst: &Path) -> io::Result<()> {
+    match rename_noreplace(src, dst) {
+        Err(ref why) if is_cross_device(why)            => {
+            let tmp = temp_path(dst);
+            let copied = copy_tree(src, &tmp).and_then(|_| rename_noreplace(&tmp, dst));
+            if let Err(why) = copied {
+                let _ = remove_path(&tmp);
+                return Err(why);
+            }
+            remove_path(src)
+        },
+        result                                          => result,
+    }
+}
+
+/// Where the copy for `dst` is made across filesystems.
+fn temp_path(dst: &Path) -> PathBuf {
+    let mut name = dst.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".rm-old-{}", std::process::id()));
+    dst.with_file_name(name)
+}
+
+#[cfg(target_os = "linux")]
+fn rename_noreplace(src: &Path, dst: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = |path: &Path| CString::new(path.as_os_str().as_bytes()).map_err(io::Error::from);
+    let (c_src, c_dst) = (c_path(src)?, c_path(dst)?);
+    let ret = unsafe {
+        libc::renameat2(libc::AT_FDCWD, c_src.as_ptr(), libc::AT_FDCWD, c_dst.as_ptr(), libc::RENAME_NOREPLACE)
+    };
+    if ret == 0 {
+        return Ok(());
+    }
+    let why = io::Error::last_os_error();
+    match why.raw_os_error() {
+        // The kernel or the filesystem does not support the flag.
+        Some(libc::EINVAL) | Some(libc::ENOSYS) => link_noreplace(src, dst),
+        _                                       => Err(why),
+    }
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn rename_noreplace(src: &Path, dst: &Path) -> io::Result<()> {
+    link_noreplace(src, dst)
+}
+
+/// Without an atomic rename: a file is hard linked at `dst` and then
+/// unlinked, and a dir is renamed onto an empty dir made at `dst`, as both
+/// link and mkdir fail if `dst` exists.
+#[cfg(unix)]
+fn link_noreplace(src: &Path, dst: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(src)?.is_dir() {
+        fs::create_dir(dst)?;
+        fs::rename(src, dst).inspect_err(|_| {
+            let _ = fs::remove_dir(dst);
+        })
+    } else {
+        fs::hard_link(src, dst)?;
+        fs::remove_file(src)
+    }
+}
+
+#[cfg(not(unix))]
+fn rename_noreplace(src: &Path, dst: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(dst).is_ok() {
+        return Err(io::Error::from(io::ErrorKind::AlreadyExists));
+    }
+    fs::rename(src, dst)
+}
+
+#[cfg(unix)]
+fn is_cross_device(why: &io::Error) -> bool {
+    why.raw_os_error() == Some(libc::EXDEV)
+}
+
+#[cfg(not(unix))]
+fn is_cross_device(why: &io::Error) -> bool {
+    why.kind() == io::ErrorKind::CrossesDevices
+}
+
+/// Remove a file, symlink or dir tree.
+pub fn remove_path(path: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// Copy a file, symlink or dir tree, keeping permissions and timestamps.
+pub fn copy_tree(src: &Path, dst: &Path) -> io::Result<()> {
+    let meta = fs::symlink_metadata(src)?;
+
+    if meta.file_type().is_symlink() {
+        return copy_symlink(src, dst);
+    }
+    if meta.is_dir() {
+        fs::create_dir(dst)?;
+        for entry in fs::read_dir(src)? {
+            let entry = entry?;
+            copy_tree(&entry.path(), &dst.join(entry.file_name()))?;
+        }
+        fs::set_permissions(dst, meta.permissions())?;
+    } else {
+        fs::copy(src, dst)?;
+    }
+    copy_times(&meta, dst)
+}
+
+fn copy_times(meta: &fs::Metadata, dst: &Path) -> io::Result<()> {
+    let mut times = FileTimes::new().set_modified(meta.modified()?);
+    if let Ok(accessed) = meta.accessed() {
+        times = times.set_accessed(accessed);
+    }
+    // Read-only and write-only files can still be opened one way or the other.
+    let file = File::open(dst).or_else(|_| File::options().write(true).open(dst))?;
+    file.set_times(times)
+}
+
+#[cfg(unix)]
+fn copy_symlink(src: &Path, dst: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(src)?, dst)
+}
+
+#[cfg(not(unix))]
+fn copy_symlink(src: &Path, _dst: &Path) -> io::Result<()> {
+    Err(io::Error::other(format!("can not copy symlink {}", src.display())))
+}
+
+
+#[cfg(test)]
+mod test{
+    use super::*;
+    use crate::testutil::TempDir;
+    use crate::arg::TimeField;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_copy_tree() {
+        let base = TempDir::new("relocate");
+        fs::create_dir_all(base.join("src/sub")).unwrap();
+        fs::write(base.join("src/sub/file"), "data").unwrap();
+        let old = SystemTime::now() - Duration::from_secs(86400);
+        File::options().write(true).open(base.join("src/sub/file")).unwrap()
+            .set_modified(old).unwrap();
+
+        copy_tree(&base.join("src"), &base.join("dst")).unwrap();
+        assert_eq!("data", fs::read_to_string(base.join("dst/sub/file")).unwrap());
+        assert_eq!(old, fs::metadata(base.join("dst/sub/file")).unwrap().modified().unwrap());
+
+        move_path(&base.join("dst"), &base.join("moved")).unwrap();
+        assert!(!base.join("dst").exists());
+        assert!(base.join("moved/sub/file").exists());
+    }
+
+    #[test]
+    fn test_move_path_noreplace() {
+        let base = TempDir::new("noreplace");
+        fs::create_dir_all(base.join("dir")).unwrap();
+        fs::write(base.join("file"), "1").unwrap();
+        fs::write(base.join("taken"), "2").unwrap();
+
+        let err = move_path_noreplace(&base.join("file"), &base.join("taken")).unwrap_err();
+        assert_eq!(io::ErrorKind::AlreadyExists, err.kind());
+        assert_eq!("2", fs::read_to_string(base.join("taken")).unwrap());
+        assert!(move_path_noreplace(&base.join("dir"), &base.join("taken")).is_err());
+
+        move_path_noreplace(&base.join("file"), &base.join("moved")).unwrap();
+        assert_eq!("1", fs::read_to_string(base.join("moved")).unwrap());
+        move_path_noreplace(&base.join("dir"), &base.join("moved-dir")).unwrap();
+        assert!(base.join("moved-dir").is_dir() && !base.join("dir").exists());
+
+        #[cfg(unix)]
+        {
+            // The fallback without renameat2 refuses the same way.
+            fs::write(base.join("file"), "3").unwrap();
+            assert_eq!(io::ErrorKind::AlreadyExists, link_noreplace(&base.join("file"), &base.join("taken")).unwrap_err().kind());
+            assert!(link_noreplace(&base.join("moved-dir"), &base.join("taken")).is_err());
+            link_noreplace(&base.join("file"), &base.join("linked")).unwrap();
+            assert!(!base.join("file").exists());
+            link_noreplace(&base.join("moved-dir"), &base.join("linked-dir")).unwrap();
+            assert!(base.join("linked-dir").is_dir());
+        }
+    }
+
+    #[test]
+    fn test_move_into() {
+        let base = TempDir::new("move-into");
+        fs::create_dir_all(base.join("data")).unwrap();
+        let root = base.join("data");
+        let rel = Path::new("file");
+        let dst = base.join("archive/sub/file");
+        let write = |data: &str| -> Expect {
+            fs::write(root.join(rel), data).unwrap();
+            let (dev, ino) = crate::fs::get_file_id(&fs::symlink_metadata(root.join(rel)).unwrap());
+            Expect { dev, ino, field: TimeField::Mtime, cutoff: SystemTime::now() + Duration::from_secs(60) }
+        };
+
+        let expect = write("1");
+        assert_eq!(Some(dst.clone()), move_into(&root, rel, &expect, &dst, Conflict::Skip).unwrap());
+
+        let expect = write("2");
+        assert_eq!(None, move_into(&root, rel, &expect, &dst, Conflict::Skip).unwrap());
+        assert!(root.join(rel).exists());
+
+        assert_eq!(Some(base.join("archive/sub/file.1")), move_into(&root, rel, &expect, &dst, Conflict::Rename).unwrap());
+        assert_eq!("2", fs::read_to_string(base.join("archive/sub/file.1")).unwrap());
+        // Each taken name is passed over, never replaced.
+        let expect = write("2b");
+        assert_eq!(Some(base.join("archive/sub/file.2")), move_into(&root, rel, &expect, &dst, Conflict::Rename).unwrap());
+        assert_eq!("2", fs::read_to_string(base.join("archive/sub/file.1")).unwrap());
+
+        let expect = write("3");
+        assert_eq!(Some(dst.clone()), move_into(&root, rel, &expect, &dst, Conflict::Overwrite).unwrap());
+        assert_eq!("3", fs::read_to_string(&dst).unwrap());
+        assert!(!root.join(rel).exists());
+
+        // A file replaced since the scan stays where it is.
+        let expect = write("4");
+        fs::write(root.join("file.new"), "5").unwrap();
+        fs::rename(root.join("file.new"), root.join(rel)).unwrap();
+        assert!(matches!(move_into(&root, rel, &expect, &base.join("other"), Conflict::Skip), Err(RemoveError::Changed(_))));
+        assert_eq!("5", fs::read_to_string(root.join(rel)).unwrap());
+        assert!(!base.join("other").exists());
+
+        #[cfg(unix)]
+        {
+            // A dir on the way swapped for a symlink is not followed.
+            fs::create_dir_all(base.join("outside/sub")).unwrap();
+            fs::write(base.join("outside/sub/f"), "x").unwrap();
+            let (dev, ino) = crate::fs::get_file_id(&fs::symlink_metadata(base.join("outside/sub/f")).unwrap());
+            let outside = Expect { dev, ino, field: TimeField::Mtime, cutoff: SystemTime::now() + Duration::from_secs(60) };
+            std::os::unix::fs::symlink(base.join("outside/sub"), root.join("sub")).unwrap();
+            assert!(matches!(move_into(&root, Path::new("sub/f"), &outside, &base.join("taken"), Conflict::Skip),
+                             Err(RemoveError::Changed(_))));
+            assert!(base.join("outside/sub/f").exists());
+        }
+    }
+}
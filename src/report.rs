@@ -0,0 +1,171 @@
+//! Machine readable output for `--output json|ndjson`: one record per
+//! candidate and per action, then a summary. With these formats stdout holds
+//! nothing else.
+
+use crate::error::Error;
+
+use serde::Serialize;
+
+use std::io::{self, Write};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Text,
+    /// One JSON array of all the records, written at the end.
+    Json,
+    /// One JSON record per line, written as they happen.
+    Ndjson,
+}
+
+impl Format {
+    pub fn parse(s: &str) -> Result<Format, Error> {
+        match s {
+            "text"      => Ok(Format::Text),
+            "json"      => Ok(Format::Json),
+            "ndjson"    => Ok(Format::Ndjson),
+            _           => Err(Error::InvalidArgument(format!("rm-old --output: Illegal value: {} (text, json or ndjson)", s))),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Format::Text    => "text",
+            Format::Json    => "json",
+            Format::Ndjson  => "ndjson",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Record {
+    /// A file selected for removal.
+    Candidate {
+        path:       String,
+        size:       u64,
+        /// The timestamp used for the age, RFC3339.
+        time:       String,
+        age_secs:   u64,
+    },
+    /// A file kept by a --keep-* rule or an ignore file.
+    Kept {
+        path:       String,
+        reason:     String,
+    },
+    /// A dir or file the scan could not read and passed over.
+    ScanError {
+        path:       String,
+        error:      String,
+    },
+    /// The selected files were packed by --archive.
+    Archive {
+        path:       String,
+        files:      usize,
+    },
+    /// What was done to a candidate: `action` is "remove", "move" or
+    /// "trash", `result` is "ok", "skipped", "canceled" or "failed".
+    Action {
+        path:       String,
+        size:       u64,
+        /// The age by the timestamp used to select it.
+        age_secs:   u64,
+        action:     &'static str,
+        result:     &'static str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        destination: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error:      Option<String>,
+        dry_run:    bool,
+    },
+    Error {
+        message:    String,
+    },
+    Summary(Summary),
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Summary {
+    pub candidates:         u64,
+    pub candidate_bytes:    u64,
+    /// Candidates removed, moved or trashed.
+    pub done:               u64,
+    pub skipped:            u64,
+    pub failed:             u64,
+    /// Dirs and files the scan passed over.
+    pub scan_errors:        u64,
+    pub reclaimed_bytes:    u64,
+    pub dry_run:            bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub journal:            Option<String>,
+}
+
+/// Writes the records in the chosen format; with `Format::Text` they are
+/// dropped, the caller printing text instead.
+pub struct Reporter {
+    format:     Format,
+    records:    Vec<Record>,
+}
+
+impl Reporter {
+    pub fn new(format: Format) -> Reporter {
+        Reporter {
+            format,
+            records:    Vec::new(),
+        }
+    }
+
+    /// Whether human readable text is to be printed.
+    pub fn is_text(&self) -> bool {
+        self.format == Format::Text
+    }
+
+    pub fn emit(&mut self, record: Record) {
+        match self.format {
+            Format::Text    => {},
+            Format::Json    => self.records.push(record),
+            Format::Ndjson  => {
+                let line = serde_json::to_string(&record).expect("records always serialize");
+                let mut out = io::stdout().lock();
+                let _ = writeln!(out, "{}", line);
+                let _ = out.flush();
+            },
+        }
+    }
+
+    /// Write out what is still held back; call once at the end.
+    pub fn finish(&mut self) {
+        if self.format == Format::Json {
+            let records = std::mem::take(&mut self.records);
+            println!("{}", serde_json::to_string_pretty(&records).expect("records always serialize"));
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        assert_eq!(Format::Ndjson, Format::parse("ndjson").unwrap());
+        assert!(Format::parse("xml").is_err());
+
+        let record = Record::Action {
+            path:           "/tmp/a".to_string(),
+            size:           10,
+            age_secs:       86400,
+            action:         "remove",
+            result:         "skipped",
+            destination:    None,
+            error:          Some("replaced since the scan".to_string()),
+            dry_run:        false,
+        };
+        assert_eq!(r#"{"type":"action","path":"/tmp/a","size":10,"age_secs":86400,"action":"remove","result":"skipped","error":"replaced since the scan","dry_run":false}"#,
+                   serde_json::to_string(&record).unwrap());
+
+        let summary = Record::Summary(Summary { candidates: 2, done: 1, ..Summary::default() });
+        assert_eq!(r#"{"type":"summary","candidates":2,"candidate_bytes":0,"done":1,"skipped":0,"failed":0,"scan_errors":0,"reclaimed_bytes":0,"dry_run":false}"#,
+                   serde_json::to_string(&summary).unwrap());
+    }
+}
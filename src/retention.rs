@@ -0,0 +1,172 @@
+use crate::fs::Entry;
+use crate::time;
+
+use std::cmp::Reverse;
+
+/// How many entries of each kind survive in a directory. Time buckets are
+/// calendar periods in UTC; the newest entry of each bucket is kept, going
+/// back until the count of the rule is used up.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Policy {
+    pub last:       Option<usize>,
+    pub daily:      Option<usize>,
+    pub weekly:     Option<usize>,
+    pub monthly:    Option<usize>,
+    pub yearly:     Option<usize>,
+}
+
+/// An entry retained by a `Policy`, with the rules that kept it, e.g.
+/// "daily 2026-10-17, weekly 2026-W42".
+#[derive(Clone, Debug)]
+pub struct Kept {
+    pub entry:  Entry,
+    pub reason: String,
+}
+
+#[derive(Clone, Copy)]
+enum Bucket {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+impl Bucket {
+    fn name(&self) -> &'static str {
+        match self {
+            Bucket::Daily   => "daily",
+            Bucket::Weekly  => "weekly",
+            Bucket::Monthly => "monthly",
+            Bucket::Yearly  => "yearly",
+        }
+    }
+
+    fn key(&self, entry: &Entry) -> String {
+        let days = time::to_unix(entry.time).div_euclid(86400);
+        let (year, month, day) = time::civil_from_days(days);
+        match self {
+            Bucket::Daily   => format!("{:04}-{:02}-{:02}", year, month, day),
+            Bucket::Weekly  => {
+                let (iso_year, week) = iso_week(days);
+                format!("{:04}-W{:02}", iso_year, week)
+            },
+            Bucket::Monthly => format!("{:04}-{:02}", year, month),
+            Bucket::Yearly  => format!("{:04}", year),
+        }
+    }
+}
+
+/// ISO 8601 (year, week) of a count of days since 1970-01-01.
+fn iso_week(days: i64) -> (i64, i64) {
+    // 1970-01-01 was a Thursday; weekday 0 is Monday.
+    let weekday = (days + 3).rem_euclid(7);
+    let thursday = days - weekday + 3;
+    let (iso_year, _, _) = time::civil_from_days(thursday);
+    let week = (thursday - time::days_from_civil(iso_year, 1, 1)) / 7 + 1;
+    (iso_year, week)
+}
+
+impl Policy {
+    pub fn is_empty(&self) -> bool {
+        self.last.is_none() && self.daily.is_none() && self.weekly.is_none()
+            && self.monthly.is_none() && self.yearly.is_none()
+    }
+
+    /// Split `entries` into those eligible for removal and those kept by the
+    /// policy. Both are returned oldest first.
+    pub fn apply(&self, mut entries: Vec<Entry>) -> (Vec<Entry>, Vec<Kept>) {
+        entries.sort_by_key(|entry| Reverse(entry.time));
+        let mut reasons: Vec<Vec<String>> = vec![Vec::new(); entries.len()];
+
+        if let Some(last) = self.last {
+            for (i, reason) in reasons.iter_mut().enumerate().take(last) {
+                reason.push(format!("last {}", i + 1));
+            }
+        }
+
+        let buckets = [(Bucket::Daily, self.daily), (Bucket::Weekly, self.weekly),
+                       (Bucket::Monthly, self.monthly), (Bucket::Yearly, self.yearly)];
+        for (bucket, count) in buckets.iter() {
+            let count = match count {
+                Some(count) => *count,
+                None        => continue,
+            };
+            let mut last_key: Option<String> = None;
+            let mut kept = 0;
+            for (entry, reason) in entries.iter().zip(reasons.iter_mut()) {
+                if kept >= count {
+                    break;
+                }
+                let key = bucket.key(entry);
+                if last_key.as_ref() != Some(&key) {
+                    reason.push(format!("{} {}", bucket.name(), key));
+                    last_key = Some(key);
+                    kept += 1;
+                }
+            }
+        }
+
+        let mut eligible = Vec::new();
+        let mut kept = Vec::new();
+        for (entry, reason) in entries.into_iter().zip(reasons).rev() {
+            if reason.is_empty() {
+                eligible.push(entry);
+            } else {
+                kept.push(Kept { entry, reason: reason.join(", ") });
+            }
+        }
+        (eligible, kept)
+    }
+}
+
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    fn entry(name: &str, date: &str) -> Entry {
+        Entry {
+            name:   name.into(),
+            time:   time::parse_datetime(date).unwrap(),
+            size:   0,
+            dev:    0,
+            ino:    0,
+        }
+    }
+
+    #[test]
+    fn test_iso_week() {
+        let days = |date: &str| time::to_unix(time::parse_datetime(date).unwrap()) / 86400;
+        assert_eq!((2026, 1), iso_week(days("2026-01-01")));
+        assert_eq!((2025, 52), iso_week(days("2025-12-28")));
+        assert_eq!((2026, 1), iso_week(days("2025-12-29")));
+        assert_eq!((2020, 53), iso_week(days("2021-01-03")));
+        assert_eq!((1970, 1), iso_week(0));
+    }
+
+    #[test]
+    fn test_apply() {
+        let entries = vec![
+            entry("a", "2026-10-17T12:00:00Z"),
+            entry("b", "2026-10-17T06:00:00Z"),
+            entry("c", "2026-10-16T06:00:00Z"),
+            entry("d", "2026-10-05T06:00:00Z"),
+            entry("e", "2026-09-30T06:00:00Z"),
+            entry("f", "2025-12-31T06:00:00Z"),
+        ];
+        let names = |list: Vec<&Entry>| list.iter().map(|e| e.name.clone()).collect::<Vec<_>>();
+
+        let policy = Policy { daily: Some(2), ..Policy::default() };
+        let (eligible, kept) = policy.apply(entries.clone());
+        assert_eq!(vec!["f", "e", "d", "b"], names(eligible.iter().collect()));
+        assert_eq!(vec!["c", "a"], names(kept.iter().map(|k| &k.entry).collect()));
+        assert_eq!("daily 2026-10-17", kept[1].reason);
+
+        let policy = Policy { last: Some(1), weekly: Some(2), monthly: Some(3), yearly: Some(2), ..Policy::default() };
+        let (eligible, kept) = policy.apply(entries);
+        assert_eq!(vec!["c", "b"], names(eligible.iter().collect()));
+        assert_eq!(vec!["f", "e", "d", "a"], names(kept.iter().map(|k| &k.entry).collect()));
+        assert_eq!("last 1, weekly 2026-W42, monthly 2026-10, yearly 2026", kept[3].reason);
+        assert_eq!("monthly 2025-12, yearly 2025", kept[0].reason);
+    }
+}
@@ -0,0 +1,309 @@
+//! The removal candidates of a run as an iterator, for library users that
+//! want to act on each file as soon as it is found, e.g. to start removing
+//! before the scan of a large tree is over.
+//!
+//! ```no_run
+//! use rm_old::arg::Config;
+//! use rm_old::scan::Scanner;
+//!
+//! let args = ["rm-old", "/var/tmp/cache", "-r", "-d", "30"];
+//! let config = Config::parse_config(&args).unwrap();
+//! for candidate in Scanner::new(&config).iter() {
+//!     match candidate {
+//!         Ok(candidate)   => println!("{} ({} bytes)", candidate.path.display(), candidate.metadata.len()),
+//!         Err(err)        => eprintln!("{}", err),
+//!     }
+//! }
+//! ```
+
+use crate::arg::Config;
+use crate::error::Error;
+use crate::fs::{self as rm_fs, Dir, Entry};
+use crate::ignore::{IgnoreStack, IGNORE_FILE};
+use crate::filter::{Age, All, And, Filter};
+
+use std::fs::{self, Metadata, ReadDir};
+use std::path::PathBuf;
+use std::time::SystemTime;
+use std::vec;
+
+/// A file selected for removal.
+#[derive(Clone, Debug)]
+pub struct Candidate {
+    /// Path of the file: `target` joined with `rel_path`.
+    pub path:       PathBuf,
+    /// The target dir the file was found in.
+    pub target:     PathBuf,
+    pub rel_path:   PathBuf,
+    pub entry:      Entry,
+    /// Its metadata, from lstat.
+    pub metadata:   Metadata,
+}
+
+/// Finds the removal candidates of a `Config`, the same files
+/// `Dir::get_target_files` selects.
+pub struct Scanner<'a> {
+    config: &'a Config,
+}
+
+impl<'a> Scanner<'a> {
+    pub fn new(config: &'a Config) -> Scanner<'a> {
+        Scanner {
+            config,
+        }
+    }
+
+    /// Whether `iter` yields each file as it is found, holding only the
+    /// open dirs on the way to it. That is the case unless the selection
+    /// needs to see a whole dir or target first: with --keep-*,
+    /// --max-total-size, --min-free, --remove-dir or --from-file the
+    /// candidates are all found before the first is yielded.
+    pub fn is_streaming(&self) -> bool {
+        self.config.policy().is_empty()
+            && self.config.max_total_size().is_none()
+            && self.config.min_free().is_none()
+            && !self.config.remove_dir()
+            && self.config.from_file().is_none()
+    }
+
+    /// The candidates, target by target and depth first. A dir that can not
+    /// be read or a file that can not be stat'ed yields an error and the
+    /// scan goes on with the rest, or with --fail-fast ends there.
+    pub fn iter(&self) -> Iter<'a> {
+        let (filter, error) = match self.config.time_window(SystemTime::now()) {
+            Ok(window)      => (Some(self.config.selection(window)), None),
+            Err(err)        => (None, Some(err)),
+        };
+        Iter {
+            config:     self.config,
+            filter,
+            error,
+            streaming:  self.is_streaming(),
+            targets:    self.config.get_target_path().into_iter(),
+            target:     PathBuf::new(),
+            stack:      Vec::new(),
+            buffered:   None,
+            stopped:    false,
+        }
+    }
+}
+
+/// A dir being read.
+struct Frame {
+    path:       PathBuf,
+    rel:        PathBuf,
+    entries:    ReadDir,
+    ignore:     IgnoreStack,
+}
+
+impl Frame {
+    fn open(path: PathBuf, rel: PathBuf, ignore: &IgnoreStack) -> Result<Frame, Error> {
+        let ignore = ignore.push_dir(&path, &rel.to_string_lossy())?;
+        let entries = fs::read_dir(&path).map_err(|why| Error::io(&path, why))?;
+        Ok(Frame {
+            path,
+            rel,
+            entries,
+            ignore,
+        })
+    }
+}
+
+pub struct Iter<'a> {
+    config:     &'a Config,
+    filter:     Option<And<Age, All>>,
+    /// An error to yield before anything else.
+    error:      Option<Error>,
+    streaming:  bool,
+    targets:    vec::IntoIter<PathBuf>,
+    target:     PathBuf,
+    stack:      Vec<Frame>,
+    /// The candidates found up front when not streaming.
+    buffered:   Option<vec::IntoIter<Result<Candidate, Error>>>,
+    /// Set by an error with --fail-fast.
+    stopped:    bool,
+}
+
+impl Iterator for Iter<'_> {
+    type Item = Result<Candidate, Error>;
+
+    fn next(&mut self) -> Option<Result<Candidate, Error>> {
+        if self.stopped {
+            return None;
+        }
+        let item = self.advance();
+        if matches!(item, Some(Err(_))) && self.config.fail_fast() {
+            self.stopped = true;
+        }
+        item
+    }
+}
+
+impl Iter<'_> {
+    fn advance(&mut self) -> Option<Result<Candidate, Error>> {
+        if let Some(err) = self.error.take() {
+            return Some(Err(err));
+        }
+        let filter = self.filter.as_ref()?;
+        if !self.streaming {
+            let config = self.config;
+            return self.buffered.get_or_insert_with(|| collect(config)).next();
+        }
+
+        loop {
+            let frame = match self.stack.last_mut() {
+                Some(frame) => frame,
+                None        => {
+                    self.target = self.targets.next()?;
+                    match Frame::open(self.target.clone(), PathBuf::new(), &IgnoreStack::new()) {
+                        Ok(frame)       => self.stack.push(frame),
+                        Err(err)        => return Some(Err(err)),
+                    }
+                    continue;
+                },
+            };
+            let file_path = match frame.entries.next() {
+                Some(Ok(dir_entry)) => dir_entry.path(),
+                Some(Err(why))      => return Some(Err(Error::io(&frame.path, why))),
+                None                => {
+                    self.stack.pop();
+                    continue;
+                },
+            };
+
+            // lstat: a symlink is an entry of its own and never leads out of the tree.
+            let file_meta = match fs::symlink_metadata(&file_path) {
+                Ok(meta)    => meta,
+                Err(why)    => return Some(Err(Error::io(&file_path, why))),
+            };
+            let file_name = file_path.file_name().unwrap_or_default().to_os_string();
+            let file_rel = frame.rel.join(&file_name);
+            let rel_text = file_rel.to_string_lossy().into_owned();
+            let name_text = file_name.to_string_lossy().into_owned();
+
+            if file_meta.is_file() || file_meta.file_type().is_symlink() {
+                if file_name == IGNORE_FILE
+                    || !rm_fs::is_included(self.config, &rel_text, &name_text)
+                    || frame.ignore.protected_by(&rel_text, false).is_some() {
+                    continue;
+                }
+                let time = match rm_fs::get_file_time(&file_meta, self.config.time_field(), &file_path) {
+                    Ok(time)        => time,
+                    Err(err)        => return Some(Err(err)),
+                };
+                if !filter.accepts(&file_path, &file_meta) {
+                    continue;
+                }
+                let (dev, ino) = rm_fs::get_file_id(&file_meta);
+                return Some(Ok(Candidate {
+                    path:       file_path,
+                    target:     self.target.clone(),
+                    rel_path:   file_rel,
+                    entry:      Entry {
+                        name:   file_name,
+                        time,
+                        size:   if file_meta.is_file() { file_meta.len() } else { 0 },
+                        dev,
+                        ino,
+                    },
+                    metadata:   file_meta,
+                }));
+            } else if file_meta.is_dir() && self.config.recursion()
+                && !rm_fs::is_excluded(self.config, &rel_text, &name_text)
+                && frame.ignore.protected_by(&rel_text, true).is_none() {
+                let ignore = frame.ignore.clone();
+                match Frame::open(file_path, file_rel, &ignore) {
+                    Ok(frame)       => self.stack.push(frame),
+                    Err(err)        => return Some(Err(err)),
+                }
+            }
+        }
+    }
+}
+
+/// The candidates of `config` found the way a run does, for the selections
+/// that can not be streamed.
+fn collect(config: &Config) -> vec::IntoIter<Result<Candidate, Error>> {
+    let dirs = match Dir::get_target_files(config) {
+        Ok(dirs)        => dirs,
+        Err(err)        => return vec![Err(err)].into_iter(),
+    };
+    let mut candidates = Vec::new();
+    for mut dir in dirs.into_iter().rev() {
+        candidates.extend(dir.skipped.drain(..).map(|skipped| Err(skipped.error)));
+        for f in dir.get_files().iter() {
+            let path = dir.get_path(&f.name);
+            candidates.push(match fs::symlink_metadata(&path) {
+                Ok(metadata)    => Ok(Candidate {
+                    target:     dir.get_target_path().to_path_buf(),
+                    rel_path:   dir.get_rel_path(&f.name),
+                    entry:      f.clone(),
+                    metadata,
+                    path,
+                }),
+                Err(why)        => Err(Error::io(&path, why)),
+            });
+        }
+    }
+    candidates.into_iter()
+}
+
+
+#[cfg(test)]
+mod test{
+    use super::*;
+    use crate::testutil::TempDir;
+    use std::fs::File;
+    use std::time::Duration;
+
+    #[test]
+    fn test_scanner() {
+        let base = TempDir::new("scan");
+        let old = SystemTime::now() - Duration::from_secs(10 * 86400);
+        for name in ["a.log", "b.txt", "new.log", "sub/c.log", "sub/deep/d.log", "skip/e.log"].iter() {
+            let path = base.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            let file = File::create(&path).unwrap();
+            file.set_len(10).unwrap();
+            if !name.starts_with("new") {
+                file.set_modified(old).unwrap();
+            }
+        }
+        fs::write(base.join(IGNORE_FILE), "skip/\n").unwrap();
+
+        let config = |args: &[&str]| {
+            let mut argv = vec!["rm-old".to_string(), base.to_str().unwrap().to_string(), "-d".to_string(), "1".to_string()];
+            argv.extend(args.iter().map(|a| a.to_string()));
+            Config::parse_config(&argv).unwrap()
+        };
+        let rel_paths = |config: &Config| {
+            let mut paths: Vec<String> = Scanner::new(config).iter()
+                .map(|candidate| candidate.unwrap().rel_path.display().to_string()).collect();
+            paths.sort();
+            paths
+        };
+
+        let streamed = config(&["-r", "--include", "*.log"]);
+        assert!(Scanner::new(&streamed).is_streaming());
+        assert_eq!(vec!["a.log", "sub/c.log", "sub/deep/d.log"], rel_paths(&streamed));
+        // The same files as a run selects.
+        let mut selected: Vec<String> = Dir::get_target_files(&streamed).unwrap().iter()
+            .flat_map(|d| d.files.iter().map(move |e| d.get_rel_path(&e.name).display().to_string())).collect();
+        selected.sort();
+        assert_eq!(selected, rel_paths(&streamed));
+
+        let kept = config(&["--keep-last", "1"]);
+        assert!(!Scanner::new(&kept).is_streaming());
+        assert_eq!(vec!["a.log", "b.txt"], rel_paths(&kept));
+
+        // Files can be removed while the scan goes on.
+        let mut removed = 0;
+        for candidate in Scanner::new(&streamed).iter() {
+            fs::remove_file(candidate.unwrap().path).unwrap();
+            removed += 1;
+        }
+        assert_eq!(3, removed);
+        assert!(rel_paths(&streamed).is_empty());
+        assert!(base.join("skip/e.log").exists());
+    }
+}
@@ -0,0 +1,79 @@
+use crate::error::Error;
+
+const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+
+/// Parse a byte count such as "512", "20G", "20GiB", "1.5T" or "500MB".
+/// K, M, G, T, P and the KiB forms are powers of 1024, KB, MB, ... are
+/// powers of 1000.
+pub fn parse_size(s: &str) -> Result<u64, Error> {
+    let illegal = || Error::InvalidArgument(format!("rm-old: Illegal size: {}", s));
+    let num_len = s.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(s.len());
+    if num_len == 0 {
+        return Err(illegal());
+    }
+    let num = s[..num_len].parse::<f64>().map_err(|_| illegal())?;
+
+    let unit = s[num_len..].trim_start();
+    let (exp, base) = match unit.to_ascii_uppercase().as_str() {
+        ""  | "B"           => (0, 1024.0),
+        "K" | "KIB"         => (1, 1024.0),
+        "M" | "MIB"         => (2, 1024.0),
+        "G" | "GIB"         => (3, 1024.0),
+        "T" | "TIB"         => (4, 1024.0),
+        "P" | "PIB"         => (5, 1024.0),
+        "KB"                => (1, 1000.0),
+        "MB"                => (2, 1000.0),
+        "GB"                => (3, 1000.0),
+        "TB"                => (4, 1000.0),
+        "PB"                => (5, 1000.0),
+        _                   => return Err(illegal()),
+    };
+
+    let bytes = num * f64::powi(base, exp);
+    if !bytes.is_finite() || bytes >= u64::MAX as f64 {
+        return Err(illegal());
+    }
+    Ok(bytes.round() as u64)
+}
+
+/// Format a byte count for humans, e.g. "1.5 GiB".
+pub fn format_size(bytes: u64) -> String {
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(512, parse_size("512").unwrap());
+        assert_eq!(512, parse_size("512B").unwrap());
+        assert_eq!(20 * 1024 * 1024 * 1024, parse_size("20G").unwrap());
+        assert_eq!(20 * 1024 * 1024 * 1024, parse_size("20GiB").unwrap());
+        assert_eq!(500_000_000, parse_size("500MB").unwrap());
+        assert_eq!(1536, parse_size("1.5k").unwrap());
+
+        for invalid in ["", "G", "10X", "1.2.3M", "-5"].iter() {
+            assert!(parse_size(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!("0 B", format_size(0));
+        assert_eq!("1023 B", format_size(1023));
+        assert_eq!("1.5 KiB", format_size(1536));
+        assert_eq!("20.0 GiB", format_size(20 * 1024 * 1024 * 1024));
+    }
+}
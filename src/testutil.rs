@@ -0,0 +1,41 @@
+//! Fixtures shared by the tests.
+
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// A fresh dir under the system temp dir, removed with all it holds when
+/// dropped, so that a failed test does not leave it behind either.
+pub struct TempDir {
+    path:   PathBuf,
+}
+
+impl TempDir {
+    /// `name` keeps apart the dirs of the tests running at the same time.
+    pub fn new(name: &str) -> TempDir {
+        let path = std::env::temp_dir().join(format!("rm-old-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir { path }
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
@@ -0,0 +1,291 @@
+use crate::error::Error;
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const MINUTE:   u64 = 60;
+const HOUR:     u64 = 60 * MINUTE;
+const DAY:      u64 = 24 * HOUR;
+const WEEK:     u64 = 7 * DAY;
+// Calendar units are approximated: a month is 30 days and a year is 365 days.
+const MONTH:    u64 = 30 * DAY;
+const YEAR:     u64 = 365 * DAY;
+
+/// One bound of the selection window, either relative to now or absolute.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Cutoff {
+    Age(Duration),
+    Date(SystemTime),
+}
+
+impl Cutoff {
+    /// Parse a duration ("36h", "1w2d") or an RFC3339 date ("2026-01-01",
+    /// "2026-01-01T12:00:00+09:00").
+    pub fn parse(s: &str) -> Result<Cutoff, Error> {
+        if looks_like_date(s) {
+            Ok(Cutoff::Date(parse_datetime(s).map_err(Error::InvalidArgument)?))
+        } else {
+            Ok(Cutoff::Age(parse_duration(s).map_err(Error::InvalidArgument)?))
+        }
+    }
+
+    pub fn to_time(&self, now: SystemTime) -> Result<SystemTime, String> {
+        match self {
+            Cutoff::Age(age)    => now.checked_sub(*age)
+                                      .ok_or_else(|| format!("rm-old: duration is too long: {}", format_duration(*age))),
+            Cutoff::Date(date)  => Ok(*date),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Cutoff::Age(age)    => format_duration(*age),
+            Cutoff::Date(date)  => format_datetime(*date),
+        }
+    }
+}
+
+/// Files whose timestamp is older than `older` and not older than `newer`
+/// are selected.
+#[derive(Clone, Copy, Debug)]
+pub struct TimeWindow {
+    pub older:  SystemTime,
+    pub newer:  Option<SystemTime>,
+}
+
+impl TimeWindow {
+    pub fn contains(&self, time: SystemTime) -> bool {
+        time < self.older && self.newer.is_none_or(|newer| time >= newer)
+    }
+}
+
+fn looks_like_date(s: &str) -> bool {
+    let b = s.as_bytes();
+    b.len() >= 10 && b[..4].iter().all(u8::is_ascii_digit) && b[4] == b'-'
+}
+
+/// Parse a duration made of `<number><unit>` pairs, e.g. "90s", "36h", "1w2d".
+/// Units: s, m, h, d, w, mo (30 days), y (365 days).
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let illegal = || format!("rm-old: Illegal duration: {}", s);
+    let mut total: u64 = 0;
+    let mut rest = s;
+
+    if rest.is_empty() {
+        return Err(illegal());
+    }
+    while !rest.is_empty() {
+        let num_len = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        let unit_len = rest[num_len..].find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(rest.len() - num_len);
+        if num_len == 0 || unit_len == 0 {
+            return Err(illegal());
+        }
+        let num = rest[..num_len].parse::<u64>().map_err(|_| illegal())?;
+        let unit = match &rest[num_len..num_len + unit_len] {
+            "s"     => 1,
+            "m"     => MINUTE,
+            "h"     => HOUR,
+            "d"     => DAY,
+            "w"     => WEEK,
+            "mo"    => MONTH,
+            "y"     => YEAR,
+            _       => return Err(illegal()),
+        };
+        total = num.checked_mul(unit)
+                   .and_then(|secs| total.checked_add(secs))
+                   .ok_or_else(illegal)?;
+        rest = &rest[num_len + unit_len..];
+    }
+
+    Ok(Duration::from_secs(total))
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs == 0 {
+        return "0s".to_string();
+    }
+    let mut out = String::new();
+    let mut rest = secs;
+    for (unit, name) in [(DAY, "d"), (HOUR, "h"), (MINUTE, "m"), (1, "s")].iter() {
+        if rest >= *unit {
+            out.push_str(&format!("{}{}", rest / unit, name));
+            rest %= unit;
+        }
+    }
+    out
+}
+
+/// Parse an RFC3339 timestamp. A bare date is taken as midnight UTC and a
+/// missing offset as UTC.
+pub fn parse_datetime(s: &str) -> Result<SystemTime, String> {
+    let illegal = || format!("rm-old: Illegal date: {} (expected RFC3339, e.g. 2026-01-01T00:00:00Z)", s);
+    let b = s.as_bytes();
+    let num = |from: usize, len: usize| -> Result<i64, String> {
+        let part = s.get(from..from + len).ok_or_else(illegal)?;
+        if !part.bytes().all(|c| c.is_ascii_digit()) {
+            return Err(illegal());
+        }
+        part.parse::<i64>().map_err(|_| illegal())
+    };
+
+    if b.len() < 10 || b[4] != b'-' || b[7] != b'-' {
+        return Err(illegal());
+    }
+    let (year, month, day) = (num(0, 4)?, num(5, 2)?, num(8, 2)?);
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return Err(illegal());
+    }
+
+    let mut secs = days_from_civil(year, month, day) * DAY as i64;
+    let mut nanos = 0;
+    if b.len() > 10 {
+        if !(b[10] == b'T' || b[10] == b't' || b[10] == b' ') || b.len() < 19 || b[13] != b':' || b[16] != b':' {
+            return Err(illegal());
+        }
+        let (hour, min, sec) = (num(11, 2)?, num(14, 2)?, num(17, 2)?);
+        if hour > 23 || min > 59 || sec > 60 {
+            return Err(illegal());
+        }
+        secs += hour * HOUR as i64 + min * MINUTE as i64 + sec;
+
+        let mut pos = 19;
+        if b.get(pos) == Some(&b'.') {
+            let frac_len = s[pos + 1..].find(|c: char| !c.is_ascii_digit()).unwrap_or(b.len() - pos - 1);
+            if frac_len == 0 {
+                return Err(illegal());
+            }
+            let frac = &s[pos + 1..pos + 1 + frac_len.min(9)];
+            nanos = frac.parse::<u32>().map_err(|_| illegal())? * 10u32.pow(9 - frac.len() as u32);
+            pos += 1 + frac_len;
+        }
+
+        match b.get(pos) {
+            None | Some(b'Z') | Some(b'z') if b.len() <= pos + 1 => {},
+            Some(b'+') | Some(b'-') if b.len() == pos + 6 && b[pos + 3] == b':' => {
+                let offset = num(pos + 1, 2)? * HOUR as i64 + num(pos + 4, 2)? * MINUTE as i64;
+                if b[pos] == b'+' {
+                    secs -= offset;
+                } else {
+                    secs += offset;
+                }
+            },
+            _ => return Err(illegal()),
+        }
+    }
+
+    Ok(from_unix(secs, nanos))
+}
+
+/// Format as RFC3339 in UTC, e.g. "2026-01-01T00:00:00Z".
+pub fn format_datetime(time: SystemTime) -> String {
+    let secs = to_unix(time);
+    let (year, month, day) = civil_from_days(secs.div_euclid(DAY as i64));
+    let rem = secs.rem_euclid(DAY as i64);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            year, month, day, rem / HOUR as i64, rem % HOUR as i64 / MINUTE as i64, rem % MINUTE as i64)
+}
+
+/// Seconds since the Unix epoch, negative for earlier times.
+pub fn to_unix(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(d)   => d.as_secs() as i64,
+        Err(e)  => -(e.duration().as_secs() as i64) - if e.duration().subsec_nanos() > 0 { 1 } else { 0 },
+    }
+}
+
+fn from_unix(secs: i64, nanos: u32) -> SystemTime {
+    if secs >= 0 {
+        UNIX_EPOCH + Duration::new(secs as u64, nanos)
+    } else {
+        UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs()) + Duration::new(0, nanos)
+    }
+}
+
+fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2               => if is_leap_year(year) { 29 } else { 28 },
+        4 | 6 | 9 | 11  => 30,
+        _               => 31,
+    }
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// (year, month, day) of a count of days since 1970-01-01.
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(Duration::from_secs(90), parse_duration("90s").unwrap());
+        assert_eq!(Duration::from_secs(36 * HOUR), parse_duration("36h").unwrap());
+        assert_eq!(Duration::from_secs(WEEK + 2 * DAY), parse_duration("1w2d").unwrap());
+        assert_eq!(Duration::from_secs(MONTH + YEAR + 5 * MINUTE), parse_duration("1y1mo5m").unwrap());
+
+        for invalid in ["", "5", "d", "3x", "1d-2h", "99999999999999999999y"].iter() {
+            assert!(parse_duration(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_parse_datetime() {
+        assert_eq!(UNIX_EPOCH, parse_datetime("1970-01-01").unwrap());
+        assert_eq!(UNIX_EPOCH + Duration::from_secs(1767225600), parse_datetime("2026-01-01T00:00:00Z").unwrap());
+        assert_eq!(parse_datetime("2026-01-01T09:00:00+09:00").unwrap(), parse_datetime("2026-01-01T00:00:00Z").unwrap());
+        assert_eq!(UNIX_EPOCH + Duration::new(1, 500_000_000), parse_datetime("1970-01-01T00:00:01.5Z").unwrap());
+        assert_eq!(UNIX_EPOCH - Duration::from_secs(DAY), parse_datetime("1969-12-31").unwrap());
+
+        for invalid in ["2026-13-01", "2026-02-30", "2026-01-01T25:00:00Z", "2026-01-01T00:00", "2026-01-01T00:00:00+0900"].iter() {
+            assert!(parse_datetime(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_civil() {
+        for days in [-719468, -1, 0, 59, 10957, 20454, 2932896].iter() {
+            let (y, m, d) = civil_from_days(*days);
+            assert_eq!(*days, days_from_civil(y, m, d));
+        }
+        assert_eq!("2026-01-01T00:00:00Z", format_datetime(parse_datetime("2026-01-01").unwrap()));
+    }
+
+    #[test]
+    fn test_time_window() {
+        let now = SystemTime::now();
+        let window = TimeWindow {
+            older:  Cutoff::parse("1d").unwrap().to_time(now).unwrap(),
+            newer:  Some(Cutoff::parse("1w").unwrap().to_time(now).unwrap()),
+        };
+        assert!(window.contains(now - Duration::from_secs(2 * DAY)));
+        assert!(!window.contains(now - Duration::from_secs(HOUR)));
+        assert!(!window.contains(now - Duration::from_secs(8 * DAY)));
+    }
+}
@@ -0,0 +1,293 @@
+//! Move files to the trash as described by the freedesktop.org Trash
+//! specification, so that desktop tools can list and restore them.
+
+use crate::error::Error;
+use crate::pathenc;
+use crate::relocate;
+use crate::unlink::{Expect, RemoveError};
+
+use std::env;
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Move `path` (a file or a dir) to the trash and return where it went.
+///
+/// The home trash (`$XDG_DATA_HOME/Trash`) is used for paths on its
+/// filesystem. Other paths go to the trash at the top of their mount,
+/// `.Trash/$UID` if the admin set it up or else `.Trash-$UID`, and failing
+/// that they are copied into the home trash.
+#[cfg(unix)]
+pub fn trash(path: &Path) -> Result<PathBuf, Error> {
+    use std::os::unix::fs::MetadataExt;
+
+    let abs = absolute(path)?;
+    let dev = fs::symlink_metadata(&abs).map_err(|why| Error::io(&abs, why))?.dev();
+    let candidates = trash_dirs(Some(&abs), dev)?;
+    put_any(&candidates, &abs, |dest| relocate::move_path(&abs, dest).map_err(|why| Error::io(&abs, why)))
+}
+
+/// Move the entry at `rel` under `root` to the trash like `trash`, if it is
+/// still what `expect` says. It is checked and moved by
+/// `relocate::move_entry`, and the trash at the top of its mount is looked
+/// for from `root` up, so nothing under `root` is followed.
+#[cfg(unix)]
+pub fn trash_checked(root: &Path, rel: &Path, expect: &Expect) -> Result<PathBuf, RemoveError> {
+    use std::os::unix::fs::MetadataExt;
+
+    let root = std::path::absolute(root).map_err(|why| Error::io(root, why))?;
+    let on_dev = fs::metadata(&root).is_ok_and(|meta| meta.dev() == expect.dev);
+    let candidates = trash_dirs(Some(root.as_path()).filter(|_| on_dev), expect.dev)?;
+    put_any(&candidates, &root.join(rel), |dest| relocate::move_entry(&root, rel, expect, dest, false))
+}
+
+#[cfg(not(unix))]
+pub fn trash(path: &Path) -> Result<PathBuf, Error> {
+    Err(Error::io(path, io::Error::new(ErrorKind::Unsupported, "can not trash on this platform")))
+}
+
+#[cfg(not(unix))]
+pub fn trash_checked(root: &Path, rel: &Path, _expect: &Expect) -> Result<PathBuf, RemoveError> {
+    Ok(trash(&root.join(rel))?)
+}
+
+/// The trash dirs to try, in order, for an item on the device `dev`.
+/// `on_dev`, a path on that device, leads to the top of its mount; without
+/// it only the home trash is tried.
+#[cfg(unix)]
+fn trash_dirs(on_dev: Option<&Path>, dev: u64) -> Result<Vec<PathBuf>, Error> {
+    use std::os::unix::fs::MetadataExt;
+
+    let home = home_trash()?;
+    let mut candidates = Vec::new();
+    if fs::create_dir_all(&home).is_ok() && fs::metadata(&home).is_ok_and(|m| m.dev() == dev) {
+        candidates.push(home);
+        return Ok(candidates);
+    }
+    if let Some(path) = on_dev {
+        let top = mount_point(path, dev);
+        let uid = unsafe { libc::getuid() };
+        let shared = top.join(".Trash");
+        if is_valid_shared_trash(&shared) {
+            candidates.push(shared.join(uid.to_string()));
+        }
+        candidates.push(top.join(format!(".Trash-{}", uid)));
+    }
+    candidates.push(home);
+    Ok(candidates)
+}
+
+/// Put `abs` into the first of `candidates` that takes it, moving it with
+/// `mv`.
+#[cfg(unix)]
+fn put_any<E, F>(candidates: &[PathBuf], abs: &Path, mut mv: F) -> Result<PathBuf, E>
+where
+    E: From<Error>,
+    F: FnMut(&Path) -> Result<(), E>,
+{
+    let mut last_err = None;
+    for trash_dir in candidates.iter() {
+        match put(trash_dir, abs, &mut mv) {
+            Ok(dest)    => return Ok(dest),
+            Err(err)    => last_err = Some(err),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| Error::io(abs, io::Error::from(ErrorKind::NotFound)).into()))
+}
+
+/// Absolute path of `path` without resolving a symlink at its end.
+fn absolute(path: &Path) -> Result<PathBuf, Error> {
+    let name = path.file_name().ok_or_else(|| Error::InvalidArgument(format!("Can not trash {}", path.display())))?;
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty()  => parent,
+        _                                               => Path::new("."),
+    };
+    let parent = fs::canonicalize(parent).map_err(|why| Error::io(path, why))?;
+    Ok(parent.join(name))
+}
+
+fn home_trash() -> Result<PathBuf, Error> {
+    Ok(data_home()?.join("Trash"))
+}
+
+/// `$XDG_DATA_HOME`, or `~/.local/share` if it is not set.
+pub(crate) fn data_home() -> Result<PathBuf, Error> {
+    match env::var_os("XDG_DATA_HOME") {
+        Some(dir) if Path::new(&dir).is_absolute()  => Ok(PathBuf::from(dir)),
+        _                                           => {
+            match env::var_os("HOME") {
+                Some(home)  => Ok(Path::new(&home).join(".local/share")),
+                None        => Err(Error::io(Path::new("$HOME"), io::Error::new(ErrorKind::NotFound, "not set"))),
+            }
+        },
+    }
+}
+
+/// The .trashinfo written by `trash` for the item moved to `dest`.
+pub fn info_path(dest: &Path) -> Option<PathBuf> {
+    let mut info_name = dest.file_name()?.to_os_string();
+    info_name.push(".trashinfo");
+    Some(dest.parent()?.parent()?.join("info").join(info_name))
+}
+
+/// The top dir of the mount holding `path`, whose device is `dev`.
+#[cfg(unix)]
+fn mount_point(path: &Path, dev: u64) -> PathBuf {
+    use std::os::unix::fs::MetadataExt;
+
+    let mut top = path;
+    while let Some(parent) = top.parent() {
+        match fs::metadata(parent) {
+            Ok(meta) if meta.dev() == dev   => top = parent,
+            _                               => break,
+        }
+    }
+    top.to_path_buf()
+}
+
+/// `$topdir/.Trash` may only be used if it is a real dir with the sticky bit.
+#[cfg(unix)]
+fn is_valid_shared_trash(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    match fs::symlink_metadata(path) {
+        Ok(meta)    => meta.is_dir() && meta.permissions().mode() & 0o1000 != 0,
+        Err(_)      => false,
+    }
+}
+
+/// Write the .trashinfo and move `abs` into `trash_dir` with `mv`, given
+/// the destination.
+fn put<E, F>(trash_dir: &Path, abs: &Path, mv: &mut F) -> Result<PathBuf, E>
+where
+    E: From<Error>,
+    F: FnMut(&Path) -> Result<(), E>,
+{
+    let files_dir = trash_dir.join("files");
+    let info_dir = trash_dir.join("info");
+    for dir in [trash_dir, files_dir.as_path(), info_dir.as_path()].iter() {
+        create_private_dir(dir).map_err(|why| Error::io(dir, why))?;
+    }
+
+    let name = abs.file_name().unwrap_or_else(|| OsStr::new("unnamed"));
+    let info = format!("[Trash Info]\nPath={}\nDeletionDate={}\n", pathenc::encode_path(abs), local_datetime(SystemTime::now()));
+
+    // Creating the .trashinfo exclusively reserves the name.
+    for n in 1.. {
+        let mut trash_name = name.to_os_string();
+        if n > 1 {
+            trash_name.push(format!(".{}", n));
+        }
+        let mut info_name = trash_name.clone();
+        info_name.push(".trashinfo");
+        let info_path = info_dir.join(&info_name);
+        let dest = files_dir.join(&trash_name);
+
+        let mut info_file = match File::options().write(true).create_new(true).open(&info_path) {
+            Ok(file)                                            => file,
+            Err(ref why) if why.kind() == ErrorKind::AlreadyExists => continue,
+            Err(why)                                            => return Err(Error::io(&info_path, why).into()),
+        };
+        if fs::symlink_metadata(&dest).is_ok() {
+            let _ = fs::remove_file(&info_path);
+            continue;
+        }
+
+        if let Err(why) = info_file.write_all(info.as_bytes()) {
+            let _ = fs::remove_file(&info_path);
+            return Err(Error::io(&info_path, why).into());
+        }
+        if let Err(err) = mv(&dest) {
+            let _ = fs::remove_file(&info_path);
+            return Err(err);
+        }
+        return Ok(dest);
+    }
+    unreachable!()
+}
+
+#[cfg(unix)]
+fn create_private_dir(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+
+    match fs::DirBuilder::new().mode(0o700).create(path) {
+        Err(ref why) if why.kind() == ErrorKind::AlreadyExists && path.is_dir() => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(not(unix))]
+fn create_private_dir(path: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(path)
+}
+
+/// "YYYY-MM-DDThh:mm:ss" in local time, the format of DeletionDate.
+#[cfg(unix)]
+fn local_datetime(time: SystemTime) -> String {
+    let secs = crate::time::to_unix(time) as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&secs, &mut tm) }.is_null() {
+        return crate::time::format_datetime(time).trim_end_matches('Z').to_string();
+    }
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            tm.tm_year + 1900, tm.tm_mon + 1, tm.tm_mday, tm.tm_hour, tm.tm_min, tm.tm_sec)
+}
+
+#[cfg(not(unix))]
+fn local_datetime(time: SystemTime) -> String {
+    crate::time::format_datetime(time).trim_end_matches('Z').to_string()
+}
+
+
+#[cfg(test)]
+mod test{
+    use super::*;
+    use crate::testutil::TempDir;
+    use crate::arg::TimeField;
+    use std::time::Duration;
+
+    #[test]
+    fn test_put() {
+        let base = TempDir::new("trash");
+        fs::create_dir_all(base.join("data/dir")).unwrap();
+        fs::write(base.join("data/a b"), "1").unwrap();
+        let data = base.join("data");
+        let trash_dir = base.join("Trash");
+        let expect = |rel: &str| {
+            let (dev, ino) = crate::fs::get_file_id(&fs::symlink_metadata(data.join(rel)).unwrap());
+            Expect { dev, ino, field: TimeField::Mtime, cutoff: SystemTime::now() + Duration::from_secs(60) }
+        };
+        let put_checked = |rel: &str, expect: &Expect| {
+            put(&trash_dir, &data.join(rel), &mut |dest: &Path| relocate::move_entry(&data, Path::new(rel), expect, dest, false))
+        };
+
+        let dest = put_checked("a b", &expect("a b")).unwrap();
+        assert_eq!(trash_dir.join("files/a b"), dest);
+        assert!(!base.join("data/a b").exists());
+        let info = fs::read_to_string(trash_dir.join("info/a b.trashinfo")).unwrap();
+        assert!(info.starts_with("[Trash Info]\n"));
+        assert!(info.contains(&format!("Path={}\n", pathenc::encode_path(&base.join("data/a b")))));
+        assert!(info.contains("DeletionDate="));
+
+        // A second item of the same name gets a new one.
+        fs::write(base.join("data/a b"), "2").unwrap();
+        let dest = put_checked("a b", &expect("a b")).unwrap();
+        assert_eq!(trash_dir.join("files/a b.2"), dest);
+        assert_eq!(Some(trash_dir.join("info/a b.2.trashinfo")), info_path(&dest));
+        assert!(trash_dir.join("info/a b.2.trashinfo").exists());
+
+        put_checked("dir", &expect("dir")).unwrap();
+        assert!(trash_dir.join("files/dir").is_dir());
+
+        // A file replaced since the scan stays, and so does no .trashinfo.
+        fs::write(base.join("data/c"), "3").unwrap();
+        let c = expect("c");
+        fs::write(base.join("data/c.new"), "4").unwrap();
+        fs::rename(base.join("data/c.new"), base.join("data/c")).unwrap();
+        assert!(matches!(put_checked("c", &c), Err(RemoveError::Changed(_))));
+        assert_eq!("4", fs::read_to_string(base.join("data/c")).unwrap());
+        assert!(!trash_dir.join("info/c.trashinfo").exists());
+    }
+}
@@ -0,0 +1,362 @@
+//! Removal that is safe against the tree changing under it.
+//!
+//! Between the scan and the removal a file can be replaced or touched, and
+//! a dir on the way to it can be swapped for a symlink, e.g. by another user
+//! of `/tmp`. So the path is walked from the target one dir at a time with
+//! `openat(O_NOFOLLOW)`, the entry is stat'ed again through the fd of its
+//! dir, and it is unlinked, or renamed away, through that same fd only if it
+//! is still the scanned file and still old enough.
+
+use crate::arg::TimeField;
+use crate::error::Error;
+
+use std::fmt;
+use std::io;
+use std::path::{Component, Path};
+use std::time::SystemTime;
+
+/// What the entry to remove must still be.
+#[derive(Clone, Copy, Debug)]
+pub struct Expect {
+    pub dev:    u64,
+    pub ino:    u64,
+    pub field:  TimeField,
+    /// The entry must still be older than this.
+    pub cutoff: SystemTime,
+}
+
+#[derive(Debug)]
+pub enum RemoveError {
+    /// The entry is not the one that was scanned any more; it is left alone.
+    Changed(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for RemoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RemoveError::Changed(reason)    => write!(f, "{}", reason),
+            RemoveError::Io(why)            => write!(f, "{}", why),
+        }
+    }
+}
+
+impl From<io::Error> for RemoveError {
+    fn from(why: io::Error) -> RemoveError {
+        RemoveError::Io(why)
+    }
+}
+
+impl From<Error> for RemoveError {
+    fn from(err: Error) -> RemoveError {
+        RemoveError::Io(err.into())
+    }
+}
+
+/// Remove the file or dir tree at `rel` under `root`, if it is still what
+/// `expect` says. Symlinks are removed, never followed; `root` itself is
+/// trusted.
+#[cfg(unix)]
+pub fn remove(root: &Path, rel: &Path, expect: &Expect) -> Result<(), RemoveError> {
+    let (dir, name, stat) = open_entry(root, rel, expect)?;
+    if stat.st_mode & libc::S_IFMT == libc::S_IFDIR {
+        sys::remove_tree_at(&dir, &name)?;
+    } else {
+        sys::unlink_at(dir.raw(), &name, 0)?;
+    }
+    Ok(())
+}
+
+/// Rename the file or dir tree at `rel` under `root` to `dst`, if it is
+/// still what `expect` says, as `remove` checks it. Unless `replace`,
+/// anything at `dst` is left alone and `AlreadyExists` returned. Across
+/// filesystems this fails with `EXDEV`.
+#[cfg(unix)]
+pub fn rename(root: &Path, rel: &Path, expect: &Expect, dst: &Path, replace: bool) -> Result<(), RemoveError> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let dst = CString::new(dst.as_os_str().as_bytes()).map_err(io::Error::from)?;
+    let (dir, name, stat) = open_entry(root, rel, expect)?;
+    sys::rename_at(dir.raw(), &name, &stat, &dst, replace)?;
+    Ok(())
+}
+
+/// Walk to the entry at `rel` under `root` and check it against `expect`,
+/// returning its dir, its name in it and its stat.
+#[cfg(unix)]
+fn open_entry(root: &Path, rel: &Path, expect: &Expect) -> Result<(sys::Dir, std::ffi::CString, libc::stat), RemoveError> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut names = Vec::new();
+    for component in rel.components() {
+        match component {
+            Component::Normal(name) => {
+                names.push(CString::new(name.as_bytes()).map_err(|_| RemoveError::Changed(format!("bad path {}", rel.display())))?);
+            },
+            Component::CurDir       => {},
+            _                       => return Err(RemoveError::Changed(format!("refusing {}", rel.display()))),
+        }
+    }
+    let name = match names.pop() {
+        Some(name)  => name,
+        None        => return Err(RemoveError::Changed(format!("bad path {}", rel.display()))),
+    };
+
+    let root = CString::new(root.as_os_str().as_bytes())
+        .map_err(|_| RemoveError::Changed(format!("bad path {}", root.display())))?;
+    let mut dir = sys::open_dir(libc::AT_FDCWD, &root, false)?;
+    for component in names.iter() {
+        // A dir swapped for a symlink fails here with ELOOP or ENOTDIR.
+        dir = sys::open_dir(dir.raw(), component, true).map_err(|why| {
+            match why.raw_os_error() {
+                Some(libc::ELOOP) | Some(libc::ENOTDIR) => {
+                    RemoveError::Changed(format!("{} is no longer a dir", component.to_string_lossy()))
+                },
+                _                                       => RemoveError::Io(why),
+            }
+        })?;
+    }
+
+    let stat = sys::stat_at(dir.raw(), &name)?;
+    if (stat.st_dev as u64, stat.st_ino as u64) != (expect.dev, expect.ino) {
+        return Err(RemoveError::Changed("replaced since the scan".to_string()));
+    }
+    // The birth time of an inode never changes, so the identity check
+    // above covers --time-field btime.
+    let time = match expect.field {
+        TimeField::Atime    => Some(sys::to_time(stat.st_atime as i64, stat.st_atime_nsec as i64)),
+        TimeField::Mtime    => Some(sys::to_time(stat.st_mtime as i64, stat.st_mtime_nsec as i64)),
+        TimeField::Ctime    => Some(sys::to_time(stat.st_ctime as i64, stat.st_ctime_nsec as i64)),
+        TimeField::Btime    => None,
+    };
+    if time.is_some_and(|time| time >= expect.cutoff) {
+        return Err(RemoveError::Changed(format!("{} is newer than the cutoff now", expect.field.name())));
+    }
+    Ok((dir, name, stat))
+}
+
+#[cfg(not(unix))]
+pub fn remove(root: &Path, rel: &Path, expect: &Expect) -> Result<(), RemoveError> {
+    let path = check_entry(root, rel, expect)?;
+    crate::relocate::remove_path(&path)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn rename(root: &Path, rel: &Path, expect: &Expect, dst: &Path, replace: bool) -> Result<(), RemoveError> {
+    let path = check_entry(root, rel, expect)?;
+    if !replace && std::fs::symlink_metadata(dst).is_ok() {
+        return Err(RemoveError::Io(io::Error::from(io::ErrorKind::AlreadyExists)));
+    }
+    std::fs::rename(&path, dst)?;
+    Ok(())
+}
+
+/// Check the entry at `rel` under `root` against `expect` by its path,
+/// returning the path.
+#[cfg(not(unix))]
+fn check_entry(root: &Path, rel: &Path, expect: &Expect) -> Result<std::path::PathBuf, RemoveError> {
+    if rel.components().any(|component| !matches!(component, Component::Normal(_) | Component::CurDir)) {
+        return Err(RemoveError::Changed(format!("refusing {}", rel.display())));
+    }
+    let path = root.join(rel);
+    let meta = std::fs::symlink_metadata(&path)?;
+    if crate::fs::get_file_id(&meta) != (expect.dev, expect.ino) {
+        return Err(RemoveError::Changed("replaced since the scan".to_string()));
+    }
+    let time = crate::fs::get_file_time(&meta, expect.field, &path).map_err(|err| RemoveError::Changed(err.to_string()))?;
+    if time >= expect.cutoff {
+        return Err(RemoveError::Changed(format!("{} is newer than the cutoff now", expect.field.name())));
+    }
+    Ok(path)
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::ffi::{CStr, CString};
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+    use std::time::{Duration, SystemTime};
+
+    pub struct Dir(OwnedFd);
+
+    impl Dir {
+        pub fn raw(&self) -> RawFd {
+            self.0.as_raw_fd()
+        }
+    }
+
+    fn check(ret: libc::c_int) -> io::Result<libc::c_int> {
+        if ret < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(ret)
+        }
+    }
+
+    pub fn open_dir(at: RawFd, name: &CStr, nofollow: bool) -> io::Result<Dir> {
+        let mut flags = libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC;
+        if nofollow {
+            flags |= libc::O_NOFOLLOW;
+        }
+        let fd = check(unsafe { libc::openat(at, name.as_ptr(), flags) })?;
+        Ok(Dir(unsafe { OwnedFd::from_raw_fd(fd) }))
+    }
+
+    pub fn stat_at(at: RawFd, name: &CStr) -> io::Result<libc::stat> {
+        let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+        check(unsafe { libc::fstatat(at, name.as_ptr(), &mut stat, libc::AT_SYMLINK_NOFOLLOW) })?;
+        Ok(stat)
+    }
+
+    pub fn unlink_at(at: RawFd, name: &CStr, flags: libc::c_int) -> io::Result<()> {
+        check(unsafe { libc::unlinkat(at, name.as_ptr(), flags) }).map(|_| ())
+    }
+
+    /// Rename `name` in `at`, whose stat is `stat`, to the path `dst`.
+    pub fn rename_at(at: RawFd, name: &CStr, stat: &libc::stat, dst: &CStr, replace: bool) -> io::Result<()> {
+        if replace {
+            return check(unsafe { libc::renameat(at, name.as_ptr(), libc::AT_FDCWD, dst.as_ptr()) }).map(|_| ());
+        }
+        #[cfg(target_os = "linux")]
+        {
+            let ret = unsafe {
+                libc::renameat2(at, name.as_ptr(), libc::AT_FDCWD, dst.as_ptr(), libc::RENAME_NOREPLACE)
+            };
+            if ret == 0 {
+                return Ok(());
+            }
+            let why = io::Error::last_os_error();
+            // Unless the kernel or the filesystem does not support the flag.
+            if !matches!(why.raw_os_error(), Some(libc::EINVAL) | Some(libc::ENOSYS)) {
+                return Err(why);
+            }
+        }
+        // Both link and mkdir fail if `dst` exists; a dir is renamed onto
+        // the empty dir made there.
+        if stat.st_mode & libc::S_IFMT == libc::S_IFDIR {
+            check(unsafe { libc::mkdirat(libc::AT_FDCWD, dst.as_ptr(), 0o700) })?;
+            check(unsafe { libc::renameat(at, name.as_ptr(), libc::AT_FDCWD, dst.as_ptr()) }).map(|_| ()).inspect_err(|_| {
+                let _ = unlink_at(libc::AT_FDCWD, dst, libc::AT_REMOVEDIR);
+            })
+        } else {
+            check(unsafe { libc::linkat(at, name.as_ptr(), libc::AT_FDCWD, dst.as_ptr(), 0) })?;
+            unlink_at(at, name, 0)
+        }
+    }
+
+    pub fn to_time(secs: i64, nsecs: i64) -> SystemTime {
+        if secs >= 0 {
+            SystemTime::UNIX_EPOCH + Duration::new(secs as u64, nsecs as u32)
+        } else {
+            SystemTime::UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs()) + Duration::from_nanos(nsecs as u64)
+        }
+    }
+
+    /// Remove the dir `name` in `parent` with everything in it, never
+    /// leaving the tree through a symlink.
+    pub fn remove_tree_at(parent: &Dir, name: &CStr) -> io::Result<()> {
+        let dir = open_dir(parent.raw(), name, true)?;
+        for (child, is_dir) in list(&dir)? {
+            if is_dir {
+                remove_tree_at(&dir, &child)?;
+            } else {
+                unlink_at(dir.raw(), &child, 0)?;
+            }
+        }
+        unlink_at(parent.raw(), name, libc::AT_REMOVEDIR)
+    }
+
+    /// The entries of `dir` and whether each is a real dir.
+    fn list(dir: &Dir) -> io::Result<Vec<(CString, bool)>> {
+        // fdopendir takes over the fd, so give it a copy.
+        let fd = check(unsafe { libc::fcntl(dir.raw(), libc::F_DUPFD_CLOEXEC, 0) })?;
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let stream = unsafe { libc::fdopendir(fd.as_raw_fd()) };
+        if stream.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        let _ = fd.into_raw_fd();
+        // The copy shares its offset with `dir`; start from the top.
+        unsafe { libc::rewinddir(stream) };
+
+        let mut entries = Vec::new();
+        let result = loop {
+            let entry = unsafe { libc::readdir(stream) };
+            if entry.is_null() {
+                break Ok(());
+            }
+            let name = unsafe { CStr::from_ptr((*entry).d_name.as_ptr()) };
+            if name.to_bytes() == b"." || name.to_bytes() == b".." {
+                continue;
+            }
+            let is_dir = match unsafe { (*entry).d_type } {
+                libc::DT_DIR        => true,
+                libc::DT_UNKNOWN    => {
+                    match stat_at(dir.raw(), name) {
+                        Ok(stat)    => stat.st_mode & libc::S_IFMT == libc::S_IFDIR,
+                        Err(why)    => break Err(why),
+                    }
+                },
+                _                   => false,
+            };
+            entries.push((name.to_owned(), is_dir));
+        };
+        unsafe { libc::closedir(stream) };
+        result.map(|_| entries)
+    }
+}
+
+
+#[cfg(all(test, unix))]
+mod test{
+    use super::*;
+    use crate::testutil::TempDir;
+    use std::fs::{self, File};
+    use std::time::Duration;
+
+    fn expect(path: &Path, cutoff: SystemTime) -> Expect {
+        let (dev, ino) = crate::fs::get_file_id(&fs::symlink_metadata(path).unwrap());
+        Expect { dev, ino, field: TimeField::Mtime, cutoff }
+    }
+
+    #[test]
+    fn test_remove() {
+        let base = TempDir::new("unlink");
+        fs::create_dir_all(base.join("t/sub/tree/deep")).unwrap();
+        fs::create_dir_all(base.join("outside/sub")).unwrap();
+        let old = SystemTime::now() - Duration::from_secs(86400);
+        for name in ["t/sub/a", "t/sub/b", "t/sub/c", "t/sub/tree/deep/x", "outside/sub/a"].iter() {
+            File::create(base.join(name)).unwrap().set_modified(old).unwrap();
+        }
+        let now = SystemTime::now();
+        let root = base.join("t");
+
+        // Untouched files and trees go.
+        let a = expect(&base.join("t/sub/a"), now);
+        remove(&root, Path::new("sub/a"), &a).unwrap();
+        assert!(!base.join("t/sub/a").exists());
+        let tree = expect(&base.join("t/sub/tree"), SystemTime::now() + Duration::from_secs(60));
+        remove(&root, Path::new("sub/tree"), &tree).unwrap();
+        assert!(!base.join("t/sub/tree").exists());
+
+        // A replaced file and a touched file stay.
+        let b = expect(&base.join("t/sub/b"), now);
+        File::create(base.join("t/sub/b.new")).unwrap().set_modified(old).unwrap();
+        fs::rename(base.join("t/sub/b.new"), base.join("t/sub/b")).unwrap();
+        assert!(matches!(remove(&root, Path::new("sub/b"), &b), Err(RemoveError::Changed(_))));
+        assert!(base.join("t/sub/b").exists());
+        let c = expect(&base.join("t/sub/c"), now);
+        File::options().write(true).open(base.join("t/sub/c")).unwrap().set_modified(SystemTime::now()).unwrap();
+        assert!(matches!(remove(&root, Path::new("sub/c"), &c), Err(RemoveError::Changed(_))));
+
+        // A dir swapped for a symlink is not followed.
+        let outside = expect(&base.join("outside/sub/a"), now);
+        fs::rename(base.join("t/sub"), base.join("t/sub.old")).unwrap();
+        std::os::unix::fs::symlink(base.join("outside/sub"), base.join("t/sub")).unwrap();
+        assert!(matches!(remove(&root, Path::new("sub/a"), &outside), Err(RemoveError::Changed(_))));
+        assert!(base.join("outside/sub/a").exists());
+    }
+}